@@ -6,13 +6,28 @@ from argparse import ArgumentParser
 from pathlib import Path
 
 
-MAIN_ORDER = ("account", "transaction_type", "rule")
-ACCOUNT_ORDER = ("name", "source_path")
+MAIN_ORDER = ("account", "category", "budget", "transaction_type", "rule")
+ACCOUNT_ORDER = ("name", "source_path", ("format",), ("include",), ("exclude",), ("max_depth",), ("kind",), ("ledger_name",))
+CATEGORY_ORDER = (
+    "name",
+    ("display_name",),
+    ("kind",),
+    ("budget",),
+)
+BUDGET_ORDER = (
+    "category",
+    "amount",
+    ("rollover",),
+    ("start",),
+    ("months",),
+)
 RULE_ORDER = (
     "transaction_type",
     "category",
     ("ignore",),
     "patterns",
+    ("tags",),
+    ("when",),
 )
 TYPE_ORDER = (
     ("mode",),
@@ -74,6 +89,12 @@ def main(config_file: Path) -> None:
             case "account":
                 order = ACCOUNT_ORDER
                 sort_key = "name"
+            case "category":
+                order = CATEGORY_ORDER
+                sort_key = "name"
+            case "budget":
+                order = BUDGET_ORDER
+                sort_key = "category"
             case "rule":
                 order = RULE_ORDER
                 sort_key = "category"
@@ -81,10 +102,18 @@ def main(config_file: Path) -> None:
                 order = TYPE_ORDER
                 sort_key = ("prefix", "source_type")
 
-        sorted_elements = sorted(  # type: ignore
-            value,
-            key=lambda v: v[sort_key] if isinstance(sort_key, str) else v.get(sort_key[0]) or v.get(sort_key[1])  # type: ignore
-        )
+        if key == "rule":
+            # Conditional rules are checked in file order, so keep them after the plain rules in their original order
+            sorted_elements = sorted(  # type: ignore
+                enumerate(value),
+                key=lambda e: (1, e[0], "") if "when" in e[1] else (0, 0, e[1][sort_key])  # type: ignore
+            )
+            sorted_elements = [v for _, v in sorted_elements]
+        else:
+            sorted_elements = sorted(  # type: ignore
+                value,
+                key=lambda v: v[sort_key] if isinstance(sort_key, str) else v.get(sort_key[0]) or v.get(sort_key[1])  # type: ignore
+            )
 
         try:
             new_config.append(key, sort_table(sorted_elements, order))  # type: ignore
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Local, Months, NaiveDate};
+use color_eyre::Result;
+use color_eyre::eyre::{Context, OptionExt, bail, eyre};
+use console::style;
+use rust_decimal::Decimal;
+
+use crate::categories::CategoryTree;
+use crate::config::BudgetConfig;
+use crate::db::Db;
+
+struct Budget {
+    category: &'static str,
+    amount: Decimal,
+    rollover: bool,
+    start: Option<NaiveDate>,
+    /// Amounts for specific months, keyed by the first day of the month
+    months: HashMap<NaiveDate, Decimal>,
+    /// Amounts repeated every year, keyed by month number
+    months_of_year: HashMap<u32, Decimal>,
+}
+
+impl Budget {
+    fn amount_for(&self, month: NaiveDate) -> Decimal {
+        self.months
+            .get(&month)
+            .or_else(|| self.months_of_year.get(&month.month()))
+            .copied()
+            .unwrap_or(self.amount)
+    }
+}
+
+/// Budget results for one category in one month
+#[derive(Debug)]
+pub struct BudgetMonth {
+    pub category: &'static str,
+    /// First day of the month
+    pub month: NaiveDate,
+    pub budgeted: Decimal,
+    /// Unspent amount carried over from the previous month
+    pub carried: Decimal,
+    pub spent: Decimal,
+    pub remaining: Decimal,
+}
+
+/// All configured budgets
+pub struct BudgetPlan {
+    budgets: Vec<Budget>,
+}
+
+impl BudgetPlan {
+    pub fn build(configs: &'static [BudgetConfig], categories: &CategoryTree) -> Result<Self> {
+        let mut budgets: Vec<Budget> = Vec::new();
+        for config in configs {
+            categories
+                .check(&config.category)
+                .wrap_err("Invalid budget")?;
+            if budgets.iter().any(|b| b.category == config.category) {
+                bail!(
+                    "Category {} has more than one [[budget]] entry",
+                    config.category
+                );
+            }
+
+            let start = config
+                .start
+                .as_deref()
+                .map(parse_month)
+                .transpose()
+                .wrap_err_with(|| format!("Invalid start for {} budget", config.category))?;
+
+            let mut months = HashMap::new();
+            let mut months_of_year = HashMap::new();
+            for (key, &amount) in &config.months {
+                if let Ok(month_of_year) = key.parse::<u32>() {
+                    if !(1..=12).contains(&month_of_year) {
+                        bail!("Invalid month {:?} in {} budget", key, config.category);
+                    }
+                    months_of_year.insert(month_of_year, amount);
+                } else {
+                    let month = parse_month(key).wrap_err_with(|| {
+                        format!("Invalid month {:?} in {} budget", key, config.category)
+                    })?;
+                    months.insert(month, amount);
+                }
+            }
+
+            budgets.push(Budget {
+                category: config.category.as_str(),
+                amount: config.amount,
+                rollover: config.rollover,
+                start,
+                months,
+                months_of_year,
+            });
+        }
+
+        // Categories can declare a fixed budget directly
+        for category in categories.iter() {
+            let Some(amount) = category.budget else {
+                continue;
+            };
+
+            if budgets.iter().any(|b| b.category == category.name) {
+                bail!(
+                    "Category {} has both a budget and a [[budget]] entry",
+                    category.name
+                );
+            }
+
+            budgets.push(Budget {
+                category: category.name,
+                amount,
+                rollover: false,
+                start: None,
+                months: HashMap::new(),
+                months_of_year: HashMap::new(),
+            });
+        }
+
+        budgets.sort_by_key(|b| b.category);
+        Ok(Self { budgets })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.budgets.is_empty()
+    }
+
+    /// Work out every budget for each month from `first` through `last`.
+    /// `spending` maps `(category, month)` to the amount spent, without rolling up sub-categories.
+    fn evaluate(
+        &self,
+        spending: &HashMap<(String, NaiveDate), Decimal>,
+        first: NaiveDate,
+        last: NaiveDate,
+    ) -> Vec<BudgetMonth> {
+        let mut results = Vec::new();
+        for budget in &self.budgets {
+            let child_prefix = format!("{}.", budget.category);
+            let mut carried = Decimal::ZERO;
+
+            let mut month = budget.start.map_or(first, |s| s.max(first));
+            while month <= last {
+                let spent = spending
+                    .iter()
+                    .filter(|((category, m), _)| {
+                        *m == month
+                            && (category == budget.category || category.starts_with(&child_prefix))
+                    })
+                    .map(|(_, amount)| *amount)
+                    .sum::<Decimal>();
+
+                let budgeted = budget.amount_for(month);
+                let remaining = budgeted + carried - spent;
+                results.push(BudgetMonth {
+                    category: budget.category,
+                    month,
+                    budgeted,
+                    carried,
+                    spent,
+                    remaining,
+                });
+
+                carried = if budget.rollover {
+                    remaining.max(Decimal::ZERO)
+                } else {
+                    Decimal::ZERO
+                };
+                month = next_month(month);
+            }
+        }
+
+        results
+    }
+}
+
+/// Recalculate every budget month up to the current month and store the results
+pub async fn refresh(db: &Db, plan: &BudgetPlan) -> Result<Vec<BudgetMonth>> {
+    let mut conn = db.open_handle().await?;
+    let this_month = first_of_month(Local::now().date_naive());
+
+    let first = conn
+        .first_posted_date()
+        .await?
+        .map_or(this_month, first_of_month);
+    let spending = conn
+        .monthly_spending(first)
+        .await?
+        .into_iter()
+        .map(|(category, month, spent)| ((category, month), spent))
+        .collect::<HashMap<_, _>>();
+
+    let results = plan.evaluate(&spending, first, this_month);
+    conn.set_budget_months(&results).await?;
+
+    Ok(results)
+}
+
+/// Print budget against spending for the most recent `months` months
+pub fn print_report(results: &[BudgetMonth], months: u32) -> Result<()> {
+    let this_month = first_of_month(Local::now().date_naive());
+    let first = this_month
+        .checked_sub_months(Months::new(months.saturating_sub(1)))
+        .ok_or_eyre("Too many months")?;
+
+    let mut month = first;
+    while month <= this_month {
+        println!("\n{}", style(month.format("%Y-%m")).bold().white());
+        println!(
+            "  {:<32} {:>12} {:>12} {:>12}",
+            "Category", "Budget", "Spent", "Remaining"
+        );
+
+        let month_results = results
+            .iter()
+            .filter(|r| r.month == month)
+            .collect::<Vec<_>>();
+
+        let mut total_budget = Decimal::ZERO;
+        let mut total_spent = Decimal::ZERO;
+        for result in &month_results {
+            let available = result.budgeted + result.carried;
+
+            // Sub-category budgets are already counted by their parent's budget
+            let nested = month_results.iter().any(|r| {
+                result
+                    .category
+                    .strip_prefix(r.category)
+                    .is_some_and(|rest| rest.starts_with('.'))
+            });
+            if !nested {
+                total_budget += available;
+                total_spent += result.spent;
+            }
+
+            let remaining = format!("{:>12.2}", result.remaining);
+            let remaining = if result.remaining.is_sign_negative() {
+                style(remaining).red()
+            } else {
+                style(remaining).green()
+            };
+            println!(
+                "  {:<32} {:>12.2} {:>12.2} {}",
+                result.category, available, result.spent, remaining
+            );
+        }
+
+        println!(
+            "  {:<32} {:>12.2} {:>12.2} {:>12.2}",
+            "Total",
+            total_budget,
+            total_spent,
+            total_budget - total_spent
+        );
+
+        month = next_month(month);
+    }
+
+    Ok(())
+}
+
+/// Parse a `YYYY-MM` month into the first day of that month
+pub fn parse_month(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d")
+        .map_err(|_| eyre!("Expected a month like 2025-01, got {:?}", value))
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap()
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    month + Months::new(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn month(year: i32, month: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+    }
+
+    fn budget(category: &'static str, amount: i64, rollover: bool) -> Budget {
+        Budget {
+            category,
+            amount: Decimal::new(amount, 0),
+            rollover,
+            start: None,
+            months: HashMap::new(),
+            months_of_year: HashMap::new(),
+        }
+    }
+
+    fn spending(entries: &[(&str, NaiveDate, i64)]) -> HashMap<(String, NaiveDate), Decimal> {
+        entries
+            .iter()
+            .map(|&(category, month, amount)| {
+                ((category.to_string(), month), Decimal::new(amount, 0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rolls_up_sub_categories() {
+        let plan = BudgetPlan {
+            budgets: vec![budget("Food", 300, false)],
+        };
+        let spending = spending(&[
+            ("Food", month(2025, 1), 50),
+            ("Food.Groceries", month(2025, 1), 200),
+            ("Foodstuff", month(2025, 1), 1000),
+            ("Food", month(2025, 2), 10),
+        ]);
+
+        let results = plan.evaluate(&spending, month(2025, 1), month(2025, 1));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].spent, Decimal::new(250, 0));
+        assert_eq!(results[0].remaining, Decimal::new(50, 0));
+    }
+
+    #[test]
+    fn carries_unspent_amounts() {
+        let plan = BudgetPlan {
+            budgets: vec![budget("Fun", 100, true), budget("Rent", 1000, false)],
+        };
+        let spending = spending(&[
+            ("Fun", month(2025, 1), 40),
+            ("Fun", month(2025, 2), 200),
+            ("Rent", month(2025, 1), 900),
+        ]);
+
+        let results = plan.evaluate(&spending, month(2025, 1), month(2025, 3));
+        let carried = |category: &str| {
+            results
+                .iter()
+                .filter(|r| r.category == category)
+                .map(|r| r.carried)
+                .collect::<Vec<_>>()
+        };
+        // Overspending isn't carried as debt
+        assert_eq!(
+            carried("Fun"),
+            [Decimal::ZERO, Decimal::new(60, 0), Decimal::ZERO]
+        );
+        assert_eq!(carried("Rent"), [Decimal::ZERO; 3]);
+    }
+
+    #[test]
+    fn month_amounts_and_start() {
+        let mut gifts = budget("Gifts", 20, false);
+        gifts.start = Some(month(2025, 11));
+        gifts.months_of_year.insert(12, Decimal::new(300, 0));
+        gifts.months.insert(month(2026, 12), Decimal::new(500, 0));
+        let plan = BudgetPlan {
+            budgets: vec![gifts],
+        };
+
+        let budgeted = plan
+            .evaluate(&HashMap::new(), month(2025, 1), month(2026, 12))
+            .into_iter()
+            .map(|r| (r.month, r.budgeted))
+            .collect::<Vec<_>>();
+        assert_eq!(budgeted.len(), 14);
+        assert_eq!(budgeted[0], (month(2025, 11), Decimal::new(20, 0)));
+        assert_eq!(budgeted[1], (month(2025, 12), Decimal::new(300, 0)));
+        assert_eq!(budgeted[13], (month(2026, 12), Decimal::new(500, 0)));
+    }
+}
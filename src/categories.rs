@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use color_eyre::Result;
+use color_eyre::eyre::bail;
+use rust_decimal::Decimal;
+
+use crate::config::{CategoryConfig, CategoryKind};
+
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub name: &'static str,
+    pub parent: Option<&'static str>,
+    /// Number of segments in the name, so top level categories have a depth of 1
+    pub depth: usize,
+    pub display_name: &'static str,
+    pub kind: CategoryKind,
+    pub budget: Option<Decimal>,
+}
+
+/// The declared `[[category]]` tree
+pub struct CategoryTree {
+    categories: BTreeMap<&'static str, Category>,
+}
+
+impl CategoryTree {
+    pub fn build(configs: &'static [CategoryConfig]) -> Result<Self> {
+        let mut declared = BTreeMap::new();
+        for config in configs {
+            if config.name.split('.').any(|s| s.trim().is_empty()) {
+                bail!("Invalid category name {:?}", config.name);
+            }
+            if declared.insert(config.name.as_str(), config).is_some() {
+                bail!("Category {:?} declared more than once", config.name);
+            }
+        }
+
+        let mut problems = Vec::new();
+        let mut categories: BTreeMap<&'static str, Category> = BTreeMap::new();
+        // Parents sort before their children, so they are always resolved first
+        for (&name, config) in &declared {
+            let parent = name.rsplit_once('.').map(|(p, _)| p);
+            let parent_category = match parent {
+                Some(p) => match categories.get(p) {
+                    Some(c) => Some(c),
+                    None => {
+                        problems.push(format!("{:?} has undeclared parent {:?}", name, p));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            let kind = match (config.kind, parent_category) {
+                (Some(kind), Some(p)) if kind != p.kind => {
+                    problems.push(format!(
+                        "{:?} is {} but its parent {:?} is {}",
+                        name,
+                        kind.name(),
+                        p.name,
+                        p.kind.name()
+                    ));
+                    continue;
+                }
+                (Some(kind), _) => kind,
+                (None, Some(p)) => p.kind,
+                (None, None) => {
+                    problems.push(format!("Top level category {:?} is missing a kind", name));
+                    continue;
+                }
+            };
+
+            categories.insert(
+                name,
+                Category {
+                    name,
+                    parent,
+                    depth: name.split('.').count(),
+                    display_name: config
+                        .display_name
+                        .as_deref()
+                        .unwrap_or_else(|| name.rsplit('.').next().unwrap()),
+                    kind,
+                    budget: config.budget,
+                },
+            );
+        }
+
+        if !problems.is_empty() {
+            bail!("Invalid categories:\n  {}", problems.join("\n  "));
+        }
+
+        Ok(Self { categories })
+    }
+
+    /// Whether categories are declared at all. Without declarations any category is accepted.
+    pub fn is_declared(&self) -> bool {
+        !self.categories.is_empty()
+    }
+
+    /// Whether `name` may be used as a category
+    pub fn accepts(&self, name: &str) -> bool {
+        !self.is_declared() || self.categories.contains_key(name)
+    }
+
+    /// Fail if `name` is not a declared category
+    pub fn check(&self, name: &str) -> Result<()> {
+        if !self.accepts(name) {
+            bail!("Undeclared category {:?}", name);
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Category> {
+        self.categories.get(name)
+    }
+
+    /// Iterate over all categories, parents before children
+    pub fn iter(&self) -> impl Iterator<Item = &Category> {
+        self.categories.values()
+    }
+}
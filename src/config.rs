@@ -1,14 +1,19 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use chrono::NaiveDate;
 use color_eyre::Result;
 use color_eyre::eyre::Context;
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
 
 use crate::importer::TransactionType;
 
+pub mod check;
+
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum UserTransactionType {
     DebitPurchase,
@@ -68,10 +73,36 @@ pub enum TransactionTypeMode {
     SourceType,
 }
 
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKind {
+    #[default]
+    Asset,
+    Liability,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AccountConfig {
     pub name: String,
     pub source_path: PathBuf,
+    /// Whether the account holds money or owes it, used by journal exports
+    #[serde(default)]
+    pub kind: AccountKind,
+    /// Full account name in journal exports, such as `Assets:Bank:Chequing`
+    #[serde(default)]
+    pub ledger_name: Option<String>,
+    /// Read every file with this format instead of detecting it, see `money formats`
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Only import files whose path below `source_path` matches one of these globs
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Skip files and directories whose path below `source_path` matches one of these globs
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Number of subdirectory levels to search, 0 only reads `source_path` itself
+    #[serde(default)]
+    pub max_depth: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash)]
@@ -98,6 +129,43 @@ pub struct TransactionTypeConfig {
     pub accounts: Vec<String>,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountSign {
+    Positive,
+    Negative,
+}
+
+/// Extra conditions a transaction must meet for a rule to apply.
+/// Amounts are compared against the absolute transaction amount.
+#[derive(Debug, Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConditionConfig {
+    #[serde(default)]
+    pub min_amount: Option<Decimal>,
+    #[serde(default)]
+    pub max_amount: Option<Decimal>,
+    #[serde(default)]
+    pub sign: Option<AmountSign>,
+    /// First posted date the rule applies to, inclusive
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub from: Option<NaiveDate>,
+    /// Posted date the rule stops applying on, exclusive
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub before: Option<NaiveDate>,
+    /// Text the memo must contain, ignoring case
+    #[serde(default)]
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub accounts: Option<Vec<String>>,
+}
+
+impl RuleConditionConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TransactionRuleConfig {
     pub transaction_type: UserTransactionType,
@@ -105,14 +173,118 @@ pub struct TransactionRuleConfig {
     #[serde(default)]
     pub ignore: bool,
     pub patterns: Vec<String>,
+    /// Labels added to every transaction the rule matches
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub when: RuleConditionConfig,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CategoryKind {
+    Income,
+    Expense,
+}
+
+impl CategoryKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Income => "income",
+            Self::Expense => "expense",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryConfig {
+    /// Full dotted path, such as `Food.Groceries`
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Required on top level categories, inherited from the parent otherwise
+    #[serde(default)]
+    pub kind: Option<CategoryKind>,
+    /// Monthly spending target
+    #[serde(default)]
+    pub budget: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    /// Category the budget covers, including its sub-categories
+    pub category: String,
+    /// Monthly amount
+    pub amount: Decimal,
+    /// Carry unspent amounts into the next month
+    #[serde(default)]
+    pub rollover: bool,
+    /// First month the budget applies to, as `YYYY-MM`
+    #[serde(default)]
+    pub start: Option<String>,
+    /// Amounts for specific months, keyed by `YYYY-MM`, or by month number to repeat every year
+    #[serde(default)]
+    pub months: BTreeMap<String, Decimal>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    Allow,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+/// Connection settings. Anything left out falls back to the `PG*` environment variables and
+/// then the Postgres defaults.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct DatabaseConfig {
-    pub host: String,
-    pub port: u16,
-    pub username: String,
-    pub password: String,
+    /// Full connection URL such as `postgres://money@db.lan/money?sslmode=require`, instead
+    /// of `host`, `port`, `socket`, `username` and `database`
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Directory holding the server's Unix socket, instead of connecting to `host`
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Plaintext password, prefer `password_env` or `password_file`. Without any of them the
+    /// password is looked up in `~/.pgpass`.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Environment variable holding the password
+    #[serde(default)]
+    pub password_env: Option<String>,
+    /// File holding the password, trailing whitespace is ignored
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+    #[serde(default)]
+    pub database: Option<String>,
+    /// Schema the tables are created in, instead of `public`
+    #[serde(default)]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub sslmode: Option<SslMode>,
+    /// CA certificate to verify the server with, for the `verify-ca` and `verify-full` modes
+    #[serde(default)]
+    pub ssl_root_cert: Option<PathBuf>,
+    /// Maximum number of open connections
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+
+fn default_pool_size() -> u32 {
+    8
 }
 
 #[derive(Debug, Deserialize)]
@@ -121,6 +293,10 @@ pub struct AppConfig {
     pub account: Vec<AccountConfig>,
     pub transaction_type: Vec<TransactionTypeConfig>,
     pub rule: Vec<TransactionRuleConfig>,
+    #[serde(default)]
+    pub category: Vec<CategoryConfig>,
+    #[serde(default)]
+    pub budget: Vec<BudgetConfig>,
 }
 
 impl AppConfig {
@@ -134,3 +310,21 @@ impl AppConfig {
         toml::from_str(&config_text).wrap_err("Malformed config file")
     }
 }
+
+/// Read a native TOML date, such as `2024-01-01`, as a `NaiveDate`
+fn deserialize_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDate>, D::Error> {
+    let Some(datetime) = Option::<toml::value::Datetime>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let date = datetime
+        .date
+        .filter(|_| datetime.time.is_none())
+        .ok_or_else(|| serde::de::Error::custom("expected a date without a time"))?;
+
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom("invalid date"))
+}
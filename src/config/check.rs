@@ -0,0 +1,459 @@
+// Checking the references between config sections, reporting every problem with its location
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::path::Path;
+
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use globset::Glob;
+use toml::de::{DeTable, DeValue};
+
+use crate::budget;
+use crate::config::{
+    AccountConfig, AppConfig, CategoryConfig, DatabaseConfig, NameSource, TransactionTypeMode,
+};
+use crate::importer::format::FileFormat;
+
+/// A problem in the config file, at a line and column counted from 1
+pub struct Problem {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// One step of the path to a value in the document
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+impl<'a> From<&'a str> for Segment<'a> {
+    fn from(key: &'a str) -> Self {
+        Self::Key(key)
+    }
+}
+
+impl From<usize> for Segment<'_> {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+/// Path to a value, like `at!["account", 0, "name"]`
+macro_rules! at {
+    ($($segment:expr),* $(,)?) => {
+        &[$(Segment::from($segment)),*]
+    };
+}
+
+struct Checker<'a> {
+    text: &'a str,
+    document: DeValue<'a>,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    /// Record a problem at the value `path` leads to, or the closest one that exists
+    fn report(&mut self, path: &[Segment], message: impl Display) {
+        let mut start = 0;
+        let mut value = &self.document;
+        for segment in path {
+            let next = match segment {
+                Segment::Key(key) => value.get(*key),
+                Segment::Index(index) => value.get(*index),
+            };
+            let Some(next) = next else {
+                break;
+            };
+            start = next.span().start;
+            value = next.get_ref();
+        }
+
+        let before = &self.text[..start];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        self.problems.push(Problem {
+            line,
+            column,
+            message: message.to_string(),
+        });
+    }
+
+    fn database(&mut self, config: &DatabaseConfig) {
+        if config.url.is_some() {
+            let parts = [
+                ("host", config.host.is_some()),
+                ("port", config.port.is_some()),
+                ("socket", config.socket.is_some()),
+                ("username", config.username.is_some()),
+                ("database", config.database.is_some()),
+            ];
+            for (key, _) in parts.into_iter().filter(|(_, set)| *set) {
+                self.report(
+                    at!["database", key],
+                    format!("{key} can't be combined with url"),
+                );
+            }
+        }
+        if let Some(url) = &config.url
+            && !url.starts_with("postgres://")
+            && !url.starts_with("postgresql://")
+        {
+            self.report(
+                at!["database", "url"],
+                "url must start with postgres:// or postgresql://",
+            );
+        }
+        if let Some(var) = &config.password_env
+            && std::env::var_os(var).is_none()
+        {
+            self.report(
+                at!["database", "password_env"],
+                format!("Environment variable {var} is not set"),
+            );
+        }
+        for (key, path) in [
+            ("password_file", &config.password_file),
+            ("ssl_root_cert", &config.ssl_root_cert),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                self.report(
+                    at!["database", key],
+                    format!("{} is not a file", path.display()),
+                );
+            }
+        }
+        if config.pool_size == 0 {
+            self.report(at!["database", "pool_size"], "pool_size must be at least 1");
+        }
+    }
+
+    fn accounts(&mut self, accounts: &[AccountConfig]) {
+        let mut names = HashSet::new();
+        for (i, account) in accounts.iter().enumerate() {
+            if !names.insert(account.name.as_str()) {
+                self.report(
+                    at!["account", i, "name"],
+                    format!("Account {:?} is declared more than once", account.name),
+                );
+            }
+            if !account.source_path.is_dir() {
+                self.report(
+                    at!["account", i, "source_path"],
+                    format!("{} is not a directory", account.source_path.display()),
+                );
+            }
+            if let Some(format) = &account.format
+                && FileFormat::from_name(format).is_none()
+            {
+                self.report(
+                    at!["account", i, "format"],
+                    format!("Unknown format {format:?}, see `money formats`"),
+                );
+            }
+            for (key, globs) in [("include", &account.include), ("exclude", &account.exclude)] {
+                for (j, glob) in globs.iter().enumerate() {
+                    if let Err(e) = Glob::new(glob) {
+                        self.report(at!["account", i, key, j], e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check the category tree, returning the declared names
+    fn categories<'c>(&mut self, configs: &'c [CategoryConfig]) -> HashSet<&'c str> {
+        let mut declared = BTreeMap::new();
+        for (i, config) in configs.iter().enumerate() {
+            if config.name.split('.').any(|s| s.trim().is_empty()) {
+                self.report(
+                    at!["category", i, "name"],
+                    format!("Invalid category name {:?}", config.name),
+                );
+            } else if declared.insert(config.name.as_str(), i).is_some() {
+                self.report(
+                    at!["category", i, "name"],
+                    format!("Category {:?} is declared more than once", config.name),
+                );
+            }
+        }
+
+        // Parents sort before their children, so their kind is always known first
+        let mut kinds = HashMap::new();
+        for (&name, &i) in &declared {
+            let parent_kind = match name.rsplit_once('.') {
+                Some((parent, _)) => {
+                    let Some(&kind) = kinds.get(parent) else {
+                        // A declared parent with problems of its own was already reported
+                        if declared.contains_key(parent) {
+                            continue;
+                        }
+                        self.report(
+                            at!["category", i, "name"],
+                            format!("{name:?} has undeclared parent {parent:?}"),
+                        );
+                        continue;
+                    };
+                    Some(kind)
+                }
+                None => None,
+            };
+
+            let kind = match (configs[i].kind, parent_kind) {
+                (Some(kind), Some(parent_kind)) if kind != parent_kind => {
+                    self.report(
+                        at!["category", i, "kind"],
+                        format!(
+                            "{name:?} is {} but its parent is {}",
+                            kind.name(),
+                            parent_kind.name()
+                        ),
+                    );
+                    continue;
+                }
+                (Some(kind), _) | (None, Some(kind)) => kind,
+                (None, None) => {
+                    self.report(
+                        at!["category", i],
+                        format!("Top level category {name:?} is missing a kind"),
+                    );
+                    continue;
+                }
+            };
+            kinds.insert(name, kind);
+        }
+
+        declared.into_keys().collect()
+    }
+
+    fn transaction_types(&mut self, config: &AppConfig) {
+        let accounts = account_names(config);
+        let mut prefixes = HashSet::new();
+        let mut source_types = HashSet::new();
+
+        for (i, type_config) in config.transaction_type.iter().enumerate() {
+            for (j, account) in type_config.accounts.iter().enumerate() {
+                if !accounts.contains(account.as_str()) {
+                    self.report(
+                        at!["transaction_type", i, "accounts", j],
+                        format!("Unknown account {account:?}"),
+                    );
+                }
+            }
+
+            match type_config.mode {
+                TransactionTypeMode::Prefix => {
+                    if type_config.source_type.is_some() {
+                        self.report(
+                            at!["transaction_type", i, "source_type"],
+                            "source_type is only used in SourceType mode",
+                        );
+                    }
+                    let Some(prefix) = &type_config.prefix else {
+                        self.report(
+                            at!["transaction_type", i],
+                            "prefix is required in Prefix mode",
+                        );
+                        continue;
+                    };
+                    for account in &type_config.accounts {
+                        if !prefixes.insert((account.as_str(), prefix.as_str())) {
+                            self.report(
+                                at!["transaction_type", i, "prefix"],
+                                format!(
+                                    "Prefix {prefix:?} is already used by another transaction type of account {account:?}"
+                                ),
+                            );
+                        }
+                    }
+                }
+                TransactionTypeMode::SourceType => {
+                    if type_config.prefix.is_some() {
+                        self.report(
+                            at!["transaction_type", i, "prefix"],
+                            "prefix is only used in Prefix mode",
+                        );
+                    }
+                    if let NameSource::NameSuffix = type_config.name_source {
+                        self.report(
+                            at!["transaction_type", i, "name_source"],
+                            "NameSuffix strips the prefix, so it can't be used in SourceType mode",
+                        );
+                    }
+                    let Some(source_type) = type_config.source_type else {
+                        self.report(
+                            at!["transaction_type", i],
+                            "source_type is required in SourceType mode",
+                        );
+                        continue;
+                    };
+                    for account in &type_config.accounts {
+                        if !source_types.insert((account.as_str(), source_type)) {
+                            self.report(
+                                at!["transaction_type", i, "source_type"],
+                                format!(
+                                    "Source type {source_type:?} is already used by another transaction type of account {account:?}"
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn rules(&mut self, config: &AppConfig, categories: &HashSet<&str>) {
+        let accounts = account_names(config);
+        let configured_types = config
+            .transaction_type
+            .iter()
+            .map(|t| t.transaction_type)
+            .collect::<HashSet<_>>();
+        let mut plain_patterns = HashSet::new();
+
+        for (i, rule) in config.rule.iter().enumerate() {
+            let type_name = rule.transaction_type.name();
+            if !configured_types.contains(&rule.transaction_type) {
+                self.report(
+                    at!["rule", i, "transaction_type"],
+                    format!(
+                        "No [[transaction_type]] produces {type_name}, so the rule never matches"
+                    ),
+                );
+            }
+            if !accepts(categories, &rule.category) {
+                self.report(
+                    at!["rule", i, "category"],
+                    format!("Undeclared category {:?}", rule.category),
+                );
+            }
+
+            if rule.when.is_empty() {
+                for (j, pattern) in rule.patterns.iter().enumerate() {
+                    if !plain_patterns.insert((rule.transaction_type, pattern.as_str())) {
+                        self.report(
+                            at!["rule", i, "patterns", j],
+                            format!("Pattern {pattern:?} already has a {type_name} rule"),
+                        );
+                    }
+                }
+            }
+
+            let when = &rule.when;
+            for (j, account) in when.accounts.iter().flatten().enumerate() {
+                if !accounts.contains(account.as_str()) {
+                    self.report(
+                        at!["rule", i, "when", "accounts", j],
+                        format!("Unknown account {account:?}"),
+                    );
+                }
+            }
+            if let (Some(min), Some(max)) = (when.min_amount, when.max_amount)
+                && min > max
+            {
+                self.report(
+                    at!["rule", i, "when", "min_amount"],
+                    format!("min_amount {min} is more than max_amount {max}"),
+                );
+            }
+            if let (Some(from), Some(before)) = (when.from, when.before)
+                && from >= before
+            {
+                self.report(
+                    at!["rule", i, "when", "from"],
+                    format!("from {from} is not before {before}"),
+                );
+            }
+        }
+    }
+
+    fn budgets(&mut self, config: &AppConfig, categories: &HashSet<&str>) {
+        let mut budgeted = HashSet::new();
+        for (i, budget) in config.budget.iter().enumerate() {
+            if !accepts(categories, &budget.category) {
+                self.report(
+                    at!["budget", i, "category"],
+                    format!("Undeclared category {:?}", budget.category),
+                );
+            }
+            if !budgeted.insert(budget.category.as_str()) {
+                self.report(
+                    at!["budget", i, "category"],
+                    format!("Category {} already has a budget", budget.category),
+                );
+            }
+            if config
+                .category
+                .iter()
+                .any(|c| c.name == budget.category && c.budget.is_some())
+            {
+                self.report(
+                    at!["budget", i, "category"],
+                    format!("Category {} also sets a budget", budget.category),
+                );
+            }
+            if let Some(start) = &budget.start
+                && let Err(e) = budget::parse_month(start)
+            {
+                self.report(at!["budget", i, "start"], e);
+            }
+            for key in budget.months.keys() {
+                let valid = match key.parse::<u32>() {
+                    Ok(month_of_year) => (1..=12).contains(&month_of_year),
+                    Err(_) => budget::parse_month(key).is_ok(),
+                };
+                if !valid {
+                    self.report(
+                        at!["budget", i, "months", key.as_str()],
+                        format!("Invalid month {key:?}, expected 1 to 12 or a month like 2025-01"),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn account_names(config: &AppConfig) -> HashSet<&str> {
+    config.account.iter().map(|a| a.name.as_str()).collect()
+}
+
+/// Without declared categories any category is accepted
+fn accepts(categories: &HashSet<&str>, name: &str) -> bool {
+    categories.is_empty() || categories.contains(name)
+}
+
+/// Check the references between sections and the settings each mode requires, which
+/// deserializing doesn't. Every problem is returned, in file order.
+pub fn check_file(path: &Path) -> Result<Vec<Problem>> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Cannot read config file at {}", path.display()))?;
+    let config: AppConfig = toml::from_str(&text).wrap_err("Malformed config file")?;
+    let document = DeTable::parse(&text)
+        .wrap_err("Malformed config file")?
+        .into_inner();
+
+    let mut checker = Checker {
+        text: &text,
+        document: DeValue::Table(document),
+        problems: Vec::new(),
+    };
+    checker.database(&config.database);
+    checker.accounts(&config.account);
+    let categories = checker.categories(&config.category);
+    checker.transaction_types(&config);
+    checker.rules(&config, &categories);
+    checker.budgets(&config, &categories);
+
+    let mut problems = checker.problems;
+    problems.sort_by_key(|p| (p.line, p.column));
+    Ok(problems)
+}
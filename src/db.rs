@@ -1,88 +1,579 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
 use color_eyre::Result;
-use color_eyre::eyre::Context;
+use color_eyre::eyre::{Context, OptionExt, bail};
+use rust_decimal::Decimal;
+use serde::Serialize;
 use sqlx::pool::{PoolConnection, PoolOptions};
-use sqlx::postgres::PgConnectOptions;
-use sqlx::{PgPool, Postgres};
+use sqlx::postgres::{PgConnectOptions, PgRow, PgSslMode};
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, Row};
+
+use crate::budget::BudgetMonth;
+use crate::categories::CategoryTree;
+use crate::config::{DatabaseConfig, IncomeType, SslMode, UserTransactionType};
+use crate::importer::categorizer::UncategorizedTransaction;
+use crate::importer::suggester::Suggestion;
+use crate::importer::{StatementBalance, Transaction, TransactionType};
+
+/// Look up the password in `$PGPASSFILE` or `~/.pgpass` for the final connection options,
+/// since sqlx only checks them against its defaults
+fn pgpass_password(options: &PgConnectOptions) -> Option<String> {
+    let path = std::env::var_os("PGPASSFILE")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".pgpass")))?;
+    let contents = std::fs::read_to_string(path).ok()?;
+
+    // Socket connections match `localhost` entries, as with libpq
+    let host = match options.get_socket() {
+        Some(_) => "localhost",
+        None => options.get_host(),
+    };
+    let port = options.get_port().to_string();
+    let wanted = [
+        host,
+        &port,
+        options.get_database().unwrap_or(options.get_username()),
+        options.get_username(),
+    ];
+
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(split_pgpass_line)
+        .find(|fields| {
+            fields.len() == 5
+                && fields
+                    .iter()
+                    .zip(wanted)
+                    .all(|(field, wanted)| field == "*" || field == wanted)
+        })
+        .map(|mut fields| fields.remove(4))
+}
+
+/// Split a `.pgpass` line on colons, which are escaped as `\:`
+fn split_pgpass_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next()),
+            ':' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Build the connection options, reading the password from wherever the config points
+fn connect_options(config: &DatabaseConfig) -> Result<PgConnectOptions> {
+    let mut options = match &config.url {
+        Some(url) => {
+            if config.host.is_some()
+                || config.port.is_some()
+                || config.socket.is_some()
+                || config.username.is_some()
+                || config.database.is_some()
+            {
+                bail!(
+                    "database.url can't be combined with host, port, socket, username or database"
+                );
+            }
+            // Also looks up ~/.pgpass when the URL has no password
+            url.parse::<PgConnectOptions>()
+                .wrap_err("Invalid database.url")?
+        }
+        None => {
+            let mut options = PgConnectOptions::new_without_pgpass();
+            if let Some(host) = &config.host {
+                options = options.host(host);
+            }
+            if let Some(port) = config.port {
+                options = options.port(port);
+            }
+            if let Some(socket) = &config.socket {
+                options = options.socket(socket);
+            }
+            if let Some(username) = &config.username {
+                options = options.username(username);
+            }
+            if let Some(database) = &config.database {
+                options = options.database(database);
+            }
+            options
+        }
+    };
 
-use crate::config::{DatabaseConfig, IncomeType};
-use crate::importer::Transaction;
-use crate::importer::categorizer::{Categorization, UncategorizedTransaction};
+    let password = match (
+        &config.password_env,
+        &config.password_file,
+        &config.password,
+    ) {
+        (Some(var), _, _) => Some(
+            std::env::var(var)
+                .wrap_err_with(|| format!("Failed to read the password from ${}", var))?,
+        ),
+        (None, Some(path), _) => Some(
+            std::fs::read_to_string(path)
+                .wrap_err_with(|| {
+                    format!("Failed to read password_file {}", path.to_string_lossy())
+                })?
+                .trim_end()
+                .to_string(),
+        ),
+        (None, None, Some(password)) => Some(password.clone()),
+        (None, None, None) if config.url.is_none() => pgpass_password(&options),
+        (None, None, None) => None,
+    };
+    if let Some(password) = password {
+        options = options.password(&password);
+    }
+
+    if let Some(mode) = config.sslmode {
+        options = options.ssl_mode(match mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        });
+    }
+    if let Some(cert) = &config.ssl_root_cert {
+        options = options.ssl_root_cert(cert);
+    }
+    if let Some(schema) = &config.schema {
+        // Startup options are split on spaces unless escaped with a backslash
+        let search_path = quote_identifier(schema)
+            .replace('\\', "\\\\")
+            .replace(' ', "\\ ");
+        options = options.options([("search_path", search_path)]);
+    }
+
+    Ok(options)
+}
+
+/// Quote `name` for use as an SQL identifier, keeping its case
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
 
 pub async fn build(config: &DatabaseConfig, clean: bool) -> Result<Db> {
-    let options = PgConnectOptions::new()
-        .host(&config.host)
-        .port(config.port)
-        .username(&config.username)
-        .password(&config.password);
+    // sqlx accepts an empty pool, which only times out waiting for a connection
+    if config.pool_size == 0 {
+        bail!("database.pool_size must be at least 1");
+    }
 
     let pool = PoolOptions::new()
-        .max_connections(8)
-        .connect_with(options)
+        .max_connections(config.pool_size)
+        .connect_with(connect_options(config)?)
         .await
         .wrap_err("Failed to open database")?;
 
     let mut conn = pool.acquire().await.wrap_err("Failed to get DB handle")?;
 
+    if let Some(schema) = &config.schema {
+        sqlx::raw_sql(&format!(
+            "CREATE SCHEMA IF NOT EXISTS {}",
+            quote_identifier(schema)
+        ))
+        .execute(&mut *conn)
+        .await
+        .wrap_err_with(|| format!("Failed to create schema {:?}", schema))?;
+    }
+
+    create_tables(&mut conn).await?;
+
     if clean {
         sqlx::raw_sql(
             "
+            INSERT INTO categorized_history (transaction_key, category, name)
+            SELECT DISTINCT ON (transaction_key) transaction_key, category, name
+            FROM transactions
+            ORDER BY transaction_key, id DESC
+            ON CONFLICT (transaction_key)
+            DO UPDATE SET category = EXCLUDED.category, name = EXCLUDED.name;
+
+            DROP VIEW IF EXISTS report_transactions;
             DROP TABLE IF EXISTS loaded_files;
             DROP TABLE IF EXISTS transactions;
             DROP TABLE IF EXISTS uncategorized_transactions;
+            DROP TABLE IF EXISTS statement_balances;
             ",
         )
         .execute(&mut *conn)
         .await
         .wrap_err("Failed to setup database tables")?;
+
+        create_tables(&mut conn).await?;
     }
 
+    Ok(Db { pool })
+}
+
+/// Create missing tables and views, and bring tables from older versions up to date
+async fn create_tables(conn: &mut PgConnection) -> Result<()> {
     sqlx::raw_sql(
         "
         CREATE TABLE IF NOT EXISTS loaded_files (
             id               serial PRIMARY KEY,
-            file_path        text NOT NULL
+            file_path        text NOT NULL,
+            file_hash        bytea
         );
 
+        -- Files used to be tracked by name alone. Those rows no longer match, and their
+        -- transactions are skipped by key if the files are read again.
+        ALTER TABLE loaded_files ADD COLUMN IF NOT EXISTS file_hash bytea;
+
+        -- Shared by transactions and uncategorized_transactions, so an id picks out one row
+        CREATE SEQUENCE IF NOT EXISTS transaction_ids;
+
         CREATE TABLE IF NOT EXISTS transactions (
-            id               serial PRIMARY KEY,
+            id               integer PRIMARY KEY DEFAULT nextval('transaction_ids'),
+            transaction_key  text NOT NULL,
             account          text NOT NULL,
             base_category    text NOT NULL,
             category         text NOT NULL,
             source_category  text,
             income           boolean,
             transaction_type text not null,
+            user_transaction_type text NOT NULL,
             posted_date      date,
             amount           NUMERIC(16, 2),
             transaction_id   text,
             name             text NOT NULL,
-            memo             text
+            memo             text,
+            rule_tags        text[] NOT NULL
         );
 
+        -- Tables created before transaction keys and rule tags. Keys are rebuilt the way
+        -- Transaction::key builds them, so existing overrides still match.
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS transaction_key text;
+        UPDATE transactions
+        SET transaction_key = CASE
+            WHEN transaction_id IS NOT NULL THEN account || ':' || transaction_id
+            ELSE account || ':' || posted_date || ':' || amount || ':' || name
+        END
+        WHERE transaction_key IS NULL;
+        ALTER TABLE transactions ALTER COLUMN transaction_key SET NOT NULL;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS user_transaction_type text;
+        UPDATE transactions SET user_transaction_type = transaction_type
+        WHERE user_transaction_type IS NULL;
+        ALTER TABLE transactions ALTER COLUMN user_transaction_type SET NOT NULL;
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS rule_tags text[] NOT NULL DEFAULT '{}';
+
         CREATE TABLE IF NOT EXISTS uncategorized_transactions (
-            id           serial PRIMARY KEY,
-            missing_rule boolean,
-            account      text NOT NULL,
-            type         text NOT NULL,
-            message      text NOT NULL
+            id                    integer PRIMARY KEY DEFAULT nextval('transaction_ids'),
+            missing_rule          boolean,
+            transaction_key       text NOT NULL,
+            account               text NOT NULL,
+            type                  text NOT NULL,
+            message               text NOT NULL,
+            transaction_type      text,
+            source_category       text,
+            posted_date           date,
+            amount                NUMERIC(16, 2),
+            transaction_id        text,
+            name                  text NOT NULL,
+            memo                  text,
+            suggestions           text[],
+            suggestion_confidence real[]
         );
+
+        -- Tables created before uncategorized transactions were stored in full.
+        -- Older rows only have the message, so they get an empty key and name.
+        ALTER TABLE uncategorized_transactions
+            ADD COLUMN IF NOT EXISTS transaction_key text NOT NULL DEFAULT '',
+            ADD COLUMN IF NOT EXISTS transaction_type text,
+            ADD COLUMN IF NOT EXISTS source_category text,
+            ADD COLUMN IF NOT EXISTS posted_date date,
+            ADD COLUMN IF NOT EXISTS amount NUMERIC(16, 2),
+            ADD COLUMN IF NOT EXISTS transaction_id text,
+            ADD COLUMN IF NOT EXISTS name text NOT NULL DEFAULT '',
+            ADD COLUMN IF NOT EXISTS memo text,
+            ADD COLUMN IF NOT EXISTS suggestions text[],
+            ADD COLUMN IF NOT EXISTS suggestion_confidence real[];
+
+        -- Tables created with their own serial ids
+        ALTER TABLE transactions ALTER COLUMN id SET DEFAULT nextval('transaction_ids');
+        ALTER TABLE uncategorized_transactions ALTER COLUMN id SET DEFAULT nextval('transaction_ids');
+        SELECT setval('transaction_ids', GREATEST(
+            (SELECT max(id) FROM transactions),
+            (SELECT max(id) FROM uncategorized_transactions),
+            (SELECT last_value FROM transaction_ids)
+        ));
+        UPDATE uncategorized_transactions SET id = nextval('transaction_ids')
+        WHERE id IN (SELECT id FROM transactions);
+
+        -- Opening and closing balances stated in imported statements, for reconciliation
+        CREATE TABLE IF NOT EXISTS statement_balances (
+            account          text NOT NULL,
+            balance_date     date NOT NULL,
+            kind             text NOT NULL,
+            amount           NUMERIC(16, 2) NOT NULL,
+            PRIMARY KEY (account, balance_date, kind)
+        );
+
+        -- Not dropped by clean, so manual categories survive re-imports
+        CREATE TABLE IF NOT EXISTS category_overrides (
+            transaction_key  text PRIMARY KEY,
+            category         text NOT NULL
+        );
+
+        -- Categories of transactions dropped by clean, so rule suggestions keep their history
+        CREATE TABLE IF NOT EXISTS categorized_history (
+            transaction_key  text PRIMARY KEY,
+            category         text NOT NULL,
+            name             text NOT NULL
+        );
+
+        -- Not dropped by clean, so splits survive re-imports
+        CREATE TABLE IF NOT EXISTS transaction_splits (
+            transaction_key  text NOT NULL,
+            position         integer NOT NULL,
+            category         text NOT NULL,
+            amount           NUMERIC(16, 2) NOT NULL,
+            PRIMARY KEY (transaction_key, position)
+        );
+
+        -- Manual tag changes, not dropped by clean.
+        -- added = false hides a tag that was applied by a rule.
+        CREATE TABLE IF NOT EXISTS transaction_tags (
+            transaction_key  text NOT NULL,
+            tag              text NOT NULL,
+            added            boolean NOT NULL,
+            PRIMARY KEY (transaction_key, tag)
+        );
+
+        -- Declared category tree, rewritten from the config on every import
+        CREATE TABLE IF NOT EXISTS categories (
+            name             text PRIMARY KEY,
+            parent           text,
+            depth            integer NOT NULL,
+            display_name     text NOT NULL,
+            kind             text NOT NULL,
+            budget           NUMERIC(16, 2)
+        );
+
+        -- Every category paired with itself and each of its ancestors, for roll-up queries
+        CREATE OR REPLACE VIEW category_ancestors AS
+        WITH RECURSIVE ancestors (category, ancestor) AS (
+            SELECT name, name FROM categories
+            UNION ALL
+            SELECT a.category, c.parent
+            FROM ancestors a
+            JOIN categories c ON c.name = a.ancestor
+            WHERE c.parent IS NOT NULL
+        )
+        SELECT a.category, a.ancestor, c.depth AS ancestor_depth
+        FROM ancestors a
+        JOIN categories c ON c.name = a.ancestor;
+
+        -- Budget results per category and month, rewritten after every import
+        CREATE TABLE IF NOT EXISTS budget_months (
+            category         text NOT NULL,
+            month            date NOT NULL,
+            budgeted         NUMERIC(16, 2) NOT NULL,
+            carried          NUMERIC(16, 2) NOT NULL,
+            spent            NUMERIC(16, 2) NOT NULL,
+            remaining        NUMERIC(16, 2) NOT NULL,
+            PRIMARY KEY (category, month)
+        );
+
+        -- One row per category a transaction's amount is assigned to.
+        -- Split transactions are replaced by their parts.
+        CREATE OR REPLACE VIEW report_transactions AS
+        SELECT
+            t.id,
+            t.transaction_key,
+            t.account,
+            split_part(COALESCE(s.category, t.category), '.', 1) AS base_category,
+            COALESCE(s.category, t.category) AS category,
+            t.source_category,
+            t.income,
+            t.transaction_type,
+            t.user_transaction_type,
+            t.posted_date,
+            COALESCE(s.amount, t.amount) AS amount,
+            t.transaction_id,
+            t.name,
+            t.memo,
+            ARRAY(
+                SELECT r.tag FROM unnest(t.rule_tags) AS r(tag)
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM transaction_tags m
+                    WHERE m.transaction_key = t.transaction_key AND m.tag = r.tag AND NOT m.added
+                )
+                UNION
+                SELECT m.tag FROM transaction_tags m
+                WHERE m.transaction_key = t.transaction_key AND m.added
+                ORDER BY 1
+            ) AS tags
+        FROM transactions t
+        LEFT JOIN transaction_splits s ON s.transaction_key = t.transaction_key;
         ",
     )
-    .execute(&mut *conn)
+    .execute(conn)
     .await
     .wrap_err("Failed to setup database tables")?;
 
-    Ok(Db { pool })
+    Ok(())
+}
+
+/// Selects which stored transactions a query covers
+#[derive(Debug, Default, Clone)]
+pub struct TransactionFilter {
+    /// First posted date, inclusive
+    pub from: Option<NaiveDate>,
+    /// Last posted date, exclusive
+    pub before: Option<NaiveDate>,
+    /// Accounts to include, or all accounts when empty
+    pub accounts: Vec<String>,
+}
+
+/// The category and related details an imported transaction is stored with
+pub struct AssignedCategory<'a> {
+    pub category: &'a str,
+    pub user_type: UserTransactionType,
+    pub income: IncomeType,
+    pub tags: &'a [String],
+}
+
+/// Conditions for searching stored transactions
+#[derive(Debug, Default, Clone)]
+pub struct TransactionSearch {
+    pub filter: TransactionFilter,
+    /// Smallest amount, compared without the sign
+    pub min_amount: Option<Decimal>,
+    /// Largest amount, compared without the sign
+    pub max_amount: Option<Decimal>,
+    /// Category to include along with its sub-categories
+    pub category: Option<String>,
+    pub transaction_type: Option<String>,
+    /// Case insensitive text to find in the name or memo
+    pub text: Option<String>,
+    /// Also search `uncategorized_transactions`
+    pub uncategorized: bool,
 }
 
 pub struct Db {
     pool: PgPool,
 }
 
+/// Rows changed by `Db::migrate_keys`
+#[derive(Debug, Default)]
+pub struct KeyMigration {
+    /// Transactions with the FITID of an earlier row, which were removed
+    pub removed_transactions: u64,
+    /// Transactions with the fingerprint of an earlier row, which were numbered
+    pub numbered_transactions: u64,
+    /// Uncategorized transactions with the key of an earlier row, which were removed
+    pub removed_uncategorized: u64,
+}
+
 impl Db {
     pub async fn open_handle(&self) -> Result<DbHandle> {
         let conn = self.pool.acquire().await?;
         Ok(DbHandle { conn })
     }
+
+    /// Make transaction keys unique, so statements that overlap don't add a transaction twice.
+    /// Tables from before keys were unique have their duplicates removed, or numbered the
+    /// way repeated fingerprints are on import.
+    pub async fn migrate_keys(&self) -> Result<KeyMigration> {
+        let mut tx = self.pool.begin().await?;
+        let mut migration = KeyMigration::default();
+
+        let transactions_key: Option<String> =
+            sqlx::query_scalar("SELECT to_regclass('transactions_key')::text;")
+                .fetch_one(&mut *tx)
+                .await?;
+        if transactions_key.is_none() {
+            migration.removed_transactions = sqlx::query(
+                "DELETE FROM transactions t
+                USING transactions d
+                WHERE d.transaction_key = t.transaction_key
+                    AND d.id < t.id
+                    AND t.transaction_id IS NOT NULL;",
+            )
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to remove duplicate transactions")?
+            .rows_affected();
+            migration.numbered_transactions = sqlx::query(
+                "UPDATE transactions t
+                SET transaction_key = t.transaction_key || ':' || d.occurrence
+                FROM (
+                    SELECT id, row_number() OVER (PARTITION BY transaction_key ORDER BY id) AS occurrence
+                    FROM transactions
+                ) d
+                WHERE d.id = t.id AND d.occurrence > 1;",
+            )
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to number repeated transactions")?
+            .rows_affected();
+            sqlx::query("CREATE UNIQUE INDEX transactions_key ON transactions (transaction_key);")
+                .execute(&mut *tx)
+                .await
+                .wrap_err("Failed to index transaction keys")?;
+        }
+
+        let uncategorized_key: Option<String> =
+            sqlx::query_scalar("SELECT to_regclass('uncategorized_transactions_key')::text;")
+                .fetch_one(&mut *tx)
+                .await?;
+        if uncategorized_key.is_none() {
+            // Rows from before keys were stored have an empty one, and are kept
+            migration.removed_uncategorized = sqlx::query(
+                "DELETE FROM uncategorized_transactions t
+                USING uncategorized_transactions d
+                WHERE d.transaction_key = t.transaction_key
+                    AND d.id < t.id
+                    AND t.transaction_key <> '';",
+            )
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to remove duplicate uncategorized transactions")?
+            .rows_affected();
+            sqlx::query(
+                "CREATE UNIQUE INDEX uncategorized_transactions_key
+                ON uncategorized_transactions (transaction_key)
+                WHERE transaction_key <> '';",
+            )
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to index uncategorized transaction keys")?;
+        }
+
+        tx.commit().await?;
+        Ok(migration)
+    }
+}
+
+/// Rebuild an imported transaction from a row of `transactions` or
+/// `uncategorized_transactions`, along with its account
+fn stored_transaction(row: &PgRow) -> Result<(String, Transaction<'static>)> {
+    let transaction_type: &str = row.try_get("transaction_type")?;
+    let transaction = Transaction {
+        transaction_type: TransactionType::from_name(transaction_type)
+            .ok_or_eyre("Unknown stored transaction type")?,
+        date_posted: row.try_get("posted_date")?,
+        amount: row.try_get("amount")?,
+        transaction_id: row
+            .try_get::<Option<String>, _>("transaction_id")?
+            .map(Into::into),
+        category: row
+            .try_get::<Option<String>, _>("source_category")?
+            .map(Into::into),
+        name: row.try_get::<String, _>("name")?.into(),
+        memo: row.try_get::<Option<String>, _>("memo")?.map(Into::into),
+    };
+
+    Ok((row.try_get("account")?, transaction))
 }
 
 pub struct DbHandle {
@@ -90,29 +581,47 @@ pub struct DbHandle {
 }
 
 impl DbHandle {
-    pub async fn add_loaded_file(&mut self, file_name: &str) -> Result<()> {
-        sqlx::query("INSERT INTO loaded_files (file_path) values ($1);")
-            .bind(file_name)
+    /// Record that the file at the canonical `file_path` was imported with contents
+    /// hashing to `file_hash`
+    pub async fn add_loaded_file(&mut self, file_path: &str, file_hash: &[u8]) -> Result<()> {
+        sqlx::query("INSERT INTO loaded_files (file_path, file_hash) values ($1, $2);")
+            .bind(file_path)
+            .bind(file_hash)
             .execute(&mut *self.conn)
             .await?;
 
         Ok(())
     }
 
-    pub async fn check_loaded_file(&mut self, file_name: &str) -> Result<bool> {
-        let existing_file = sqlx::query("SELECT (id) FROM loaded_files WHERE file_path = $1;")
-            .bind(file_name)
-            .fetch_optional(&mut *self.conn)
-            .await?;
+    /// Whether a file with the same contents was already imported, from `file_path` if set
+    /// or from anywhere otherwise
+    pub async fn check_loaded_file(
+        &mut self,
+        file_path: Option<&str>,
+        file_hash: &[u8],
+    ) -> Result<bool> {
+        let existing_file = sqlx::query(
+            "SELECT (id) FROM loaded_files
+            WHERE ($1::text IS NULL OR file_path = $1) AND file_hash = $2;",
+        )
+        .bind(file_path)
+        .bind(file_hash)
+        .fetch_optional(&mut *self.conn)
+        .await?;
 
         Ok(existing_file.is_some())
     }
 
+    /// Store a transaction no rule categorized, returning false when one with the same key
+    /// is already stored
     pub async fn add_uncategorized_transaction(
         &mut self,
-        transaction: UncategorizedTransaction,
-    ) -> Result<()> {
-        let (missing_rule, account, missing_type, message) = match transaction {
+        transaction_key: &str,
+        uncategorized: UncategorizedTransaction,
+        transaction: &Transaction<'_>,
+        suggestions: &[Suggestion],
+    ) -> Result<bool> {
+        let (missing_rule, account, missing_type, message) = match uncategorized {
             UncategorizedTransaction::MissingType {
                 account,
                 source_type,
@@ -122,58 +631,95 @@ impl DbHandle {
                 account,
                 transaction_type,
                 display,
+                ..
             } => (true, account, transaction_type.name(), display),
         };
 
-        sqlx::query(
+        let result = sqlx::query(
             "INSERT INTO uncategorized_transactions (
                 missing_rule,
+                transaction_key,
                 account,
                 type,
-                message
+                message,
+                transaction_type,
+                source_category,
+                posted_date,
+                amount,
+                transaction_id,
+                name,
+                memo,
+                suggestions,
+                suggestion_confidence
             ) values (
                 $1,
                 $2,
                 $3,
-                $4
-            );",
+                $4,
+                $5,
+                $6,
+                $7,
+                $8,
+                $9,
+                $10,
+                $11,
+                $12,
+                $13,
+                $14
+            )
+            ON CONFLICT DO NOTHING;",
         )
         .bind(missing_rule)
+        .bind(transaction_key)
         .bind(account)
         .bind(missing_type)
         .bind(message)
+        .bind(transaction.transaction_type.name())
+        .bind(transaction.category.as_deref())
+        .bind(transaction.date_posted)
+        .bind(transaction.amount)
+        .bind(transaction.transaction_id.as_deref())
+        .bind(transaction.name.as_ref())
+        .bind(transaction.memo.as_deref())
+        .bind(suggestions.iter().map(|s| s.category).collect::<Vec<_>>())
+        .bind(suggestions.iter().map(|s| s.confidence).collect::<Vec<_>>())
         .execute(&mut *self.conn)
         .await?;
 
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
 
-    pub async fn add_transaction<'t>(
-        &'t mut self,
+    /// Store a categorized transaction, returning false when one with the same key is
+    /// already stored
+    pub async fn add_transaction(
+        &mut self,
         account: &str,
-        categorization: Categorization,
-        transaction: Transaction<'t>,
-    ) -> Result<()> {
-        let base_category = categorization.category.split('.').next().unwrap();
-        let income = match categorization.income {
+        transaction_key: &str,
+        assigned: &AssignedCategory<'_>,
+        transaction: Transaction<'_>,
+    ) -> Result<bool> {
+        let income = match assigned.income {
             IncomeType::Yes => true,
             IncomeType::No => false,
             IncomeType::Auto => transaction.amount.is_sign_positive(),
         };
 
-        sqlx::query(
+        let result = sqlx::query(
             "INSERT INTO transactions (
+                transaction_key,
                 account,
                 base_category,
                 category,
                 source_category,
                 income,
                 transaction_type,
+                user_transaction_type,
                 posted_date,
                 amount,
                 transaction_id,
                 name,
-                memo
+                memo,
+                rule_tags
             ) values (
                 $1,
                 $2,
@@ -185,24 +731,684 @@ impl DbHandle {
                 $8,
                 $9,
                 $10,
-                $11
-            );",
+                $11,
+                $12,
+                $13,
+                $14
+            )
+            ON CONFLICT DO NOTHING;",
         )
+        .bind(transaction_key)
         .bind(account)
-        .bind(base_category)
-        .bind(categorization.category)
+        .bind(base_category(assigned.category))
+        .bind(assigned.category)
         .bind(transaction.category)
         .bind(income)
         .bind(transaction.transaction_type.name())
+        .bind(assigned.user_type.name())
         .bind(transaction.date_posted)
         .bind(transaction.amount)
         .bind(transaction.transaction_id)
         .bind(transaction.name)
         .bind(transaction.memo)
+        .bind(assigned.tags)
         .execute(&mut *self.conn)
         .await
         .wrap_err("Failed to add transaction")?;
 
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_statement_balance(
+        &mut self,
+        account: &str,
+        balance: &StatementBalance,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO statement_balances (account, balance_date, kind, amount)
+            values ($1, $2, $3, $4)
+            ON CONFLICT (account, balance_date, kind) DO UPDATE SET amount = EXCLUDED.amount;",
+        )
+        .bind(account)
+        .bind(balance.date)
+        .bind(balance.kind.name())
+        .bind(balance.amount)
+        .execute(&mut *self.conn)
+        .await
+        .wrap_err("Failed to add statement balance")?;
+
+        Ok(())
+    }
+
+    /// Replace the stored category tree with the declared one
+    pub async fn sync_categories(&mut self, categories: &CategoryTree) -> Result<()> {
+        let mut tx = self.conn.begin().await?;
+
+        sqlx::query("DELETE FROM categories;")
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to clear categories")?;
+
+        for category in categories.iter() {
+            sqlx::query(
+                "INSERT INTO categories (
+                    name,
+                    parent,
+                    depth,
+                    display_name,
+                    kind,
+                    budget
+                ) values (
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    $5,
+                    $6
+                );",
+            )
+            .bind(category.name)
+            .bind(category.parent)
+            .bind(i32::try_from(category.depth)?)
+            .bind(category.display_name)
+            .bind(category.kind.name())
+            .bind(category.budget)
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to add category")?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Total amounts per category, income flag and month, as `(category, income, month, amount)`
+    pub async fn monthly_totals(
+        &mut self,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<(String, bool, NaiveDate, Decimal)>> {
+        sqlx::query_as(
+            "SELECT
+                category,
+                income,
+                date_trunc('month', posted_date)::date AS month,
+                SUM(amount) AS amount
+            FROM report_transactions
+            WHERE ($1::date IS NULL OR posted_date >= $1)
+            AND ($2::date IS NULL OR posted_date < $2)
+            AND (cardinality($3::text[]) = 0 OR account = ANY($3))
+            GROUP BY category, income, month
+            ORDER BY month;",
+        )
+        .bind(filter.from)
+        .bind(filter.before)
+        .bind(&filter.accounts)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to total transactions")
+    }
+
+    pub async fn first_posted_date(&mut self) -> Result<Option<NaiveDate>> {
+        sqlx::query_scalar("SELECT MIN(posted_date) FROM transactions;")
+            .fetch_one(&mut *self.conn)
+            .await
+            .wrap_err("Failed to find first transaction")
+    }
+
+    /// Total spending per category and month since `from`, as `(category, month, spent)`
+    pub async fn monthly_spending(
+        &mut self,
+        from: NaiveDate,
+    ) -> Result<Vec<(String, NaiveDate, Decimal)>> {
+        sqlx::query_as(
+            "SELECT
+                category,
+                date_trunc('month', posted_date)::date AS month,
+                -SUM(amount) AS spent
+            FROM report_transactions
+            WHERE NOT income AND posted_date >= $1
+            GROUP BY category, month;",
+        )
+        .bind(from)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to total monthly spending")
+    }
+
+    /// Replace the stored budget results
+    pub async fn set_budget_months(&mut self, results: &[BudgetMonth]) -> Result<()> {
+        let mut tx = self.conn.begin().await?;
+
+        sqlx::query("DELETE FROM budget_months;")
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to clear budget results")?;
+
+        for result in results {
+            sqlx::query(
+                "INSERT INTO budget_months (
+                    category,
+                    month,
+                    budgeted,
+                    carried,
+                    spent,
+                    remaining
+                ) values (
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    $5,
+                    $6
+                );",
+            )
+            .bind(result.category)
+            .bind(result.month)
+            .bind(result.budgeted)
+            .bind(result.carried)
+            .bind(result.spent)
+            .bind(result.remaining)
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to add budget result")?;
+        }
+
+        tx.commit().await?;
         Ok(())
     }
+
+    /// Change the category of every stored transaction with the given key
+    pub async fn set_transaction_category(
+        &mut self,
+        transaction_key: &str,
+        category: &str,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE transactions SET base_category = $2, category = $3 WHERE transaction_key = $1;",
+        )
+        .bind(transaction_key)
+        .bind(base_category(category))
+        .bind(category)
+        .execute(&mut *self.conn)
+        .await
+        .wrap_err("Failed to update transaction category")?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn remove_transactions(&mut self, transaction_key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM transactions WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .execute(&mut *self.conn)
+            .await
+            .wrap_err("Failed to remove transaction")?;
+
+        Ok(())
+    }
+
+    pub async fn get_transaction_amount(
+        &mut self,
+        transaction_key: &str,
+    ) -> Result<Option<Decimal>> {
+        sqlx::query_scalar("SELECT amount FROM transactions WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .fetch_optional(&mut *self.conn)
+            .await
+            .wrap_err("Failed to look up transaction")
+    }
+
+    /// Replace the split parts of a transaction
+    pub async fn set_splits(
+        &mut self,
+        transaction_key: &str,
+        parts: &[(String, Decimal)],
+    ) -> Result<()> {
+        let mut tx = self.conn.begin().await?;
+
+        sqlx::query("DELETE FROM transaction_splits WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to remove old splits")?;
+
+        for (position, (category, amount)) in parts.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO transaction_splits (
+                    transaction_key,
+                    position,
+                    category,
+                    amount
+                ) values (
+                    $1,
+                    $2,
+                    $3,
+                    $4
+                );",
+            )
+            .bind(transaction_key)
+            .bind(i32::try_from(position)?)
+            .bind(category)
+            .bind(amount)
+            .execute(&mut *tx)
+            .await
+            .wrap_err("Failed to add split")?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Remove the split parts of a transaction, returning whether it was split
+    pub async fn clear_splits(&mut self, transaction_key: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM transaction_splits WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .execute(&mut *self.conn)
+            .await
+            .wrap_err("Failed to clear splits")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// List the keys of stored transactions posted in a date range, optionally limited to one account
+    pub async fn list_transaction_keys(
+        &mut self,
+        from: Option<NaiveDate>,
+        before: Option<NaiveDate>,
+        account: Option<&str>,
+    ) -> Result<Vec<String>> {
+        sqlx::query_scalar(
+            "SELECT DISTINCT transaction_key FROM transactions
+            WHERE ($1::date IS NULL OR posted_date >= $1)
+            AND ($2::date IS NULL OR posted_date < $2)
+            AND ($3::text IS NULL OR account = $3);",
+        )
+        .bind(from)
+        .bind(before)
+        .bind(account)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list transactions")
+    }
+
+    /// Manually add or remove tags on the given transactions
+    pub async fn set_tags(
+        &mut self,
+        transaction_keys: &[String],
+        tags: &[String],
+        added: bool,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO transaction_tags (transaction_key, tag, added)
+            SELECT k, t, $3 FROM unnest($1::text[]) AS k CROSS JOIN unnest($2::text[]) AS t
+            ON CONFLICT (transaction_key, tag) DO UPDATE SET added = EXCLUDED.added;",
+        )
+        .bind(transaction_keys)
+        .bind(tags)
+        .bind(added)
+        .execute(&mut *self.conn)
+        .await
+        .wrap_err("Failed to update tags")?;
+
+        Ok(())
+    }
+
+    pub async fn get_transaction_key(&mut self, id: i32) -> Result<Option<String>> {
+        sqlx::query_scalar(
+            "SELECT transaction_key FROM transactions WHERE id = $1
+            UNION ALL
+            SELECT transaction_key FROM uncategorized_transactions WHERE id = $1;",
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn)
+        .await
+        .wrap_err("Failed to look up transaction")
+    }
+
+    pub async fn list_overrides(&mut self) -> Result<HashMap<String, String>> {
+        let overrides: Vec<(String, String)> =
+            sqlx::query_as("SELECT transaction_key, category FROM category_overrides;")
+                .fetch_all(&mut *self.conn)
+                .await
+                .wrap_err("Failed to list category overrides")?;
+
+        Ok(overrides.into_iter().collect())
+    }
+
+    pub async fn set_override(&mut self, transaction_key: &str, category: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO category_overrides (transaction_key, category) values ($1, $2)
+            ON CONFLICT (transaction_key) DO UPDATE SET category = EXCLUDED.category;",
+        )
+        .bind(transaction_key)
+        .bind(category)
+        .execute(&mut *self.conn)
+        .await
+        .wrap_err("Failed to set category override")?;
+
+        Ok(())
+    }
+
+    /// Remove an override, returning whether one existed
+    pub async fn clear_override(&mut self, transaction_key: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM category_overrides WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .execute(&mut *self.conn)
+            .await
+            .wrap_err("Failed to clear category override")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Load stored transactions along with the account they belong to.
+    /// Loads every transaction unless `transaction_key` is set.
+    pub async fn list_transactions(
+        &mut self,
+        transaction_key: Option<&str>,
+    ) -> Result<Vec<(String, Transaction<'static>)>> {
+        let rows = sqlx::query(
+            "SELECT
+                account,
+                source_category,
+                transaction_type,
+                posted_date,
+                amount,
+                transaction_id,
+                name,
+                memo
+            FROM transactions
+            WHERE $1::text IS NULL OR transaction_key = $1
+            ORDER BY id;",
+        )
+        .bind(transaction_key)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list transactions")?;
+
+        rows.iter().map(stored_transaction).collect()
+    }
+
+    /// Load uncategorized transactions along with the account they belong to.
+    /// Loads every transaction unless `transaction_key` is set. Rows stored before
+    /// transactions were kept in full are left out.
+    pub async fn list_uncategorized_transactions(
+        &mut self,
+        transaction_key: Option<&str>,
+    ) -> Result<Vec<(String, Transaction<'static>)>> {
+        let rows = sqlx::query(
+            "SELECT
+                account,
+                source_category,
+                transaction_type,
+                posted_date,
+                amount,
+                transaction_id,
+                name,
+                memo
+            FROM uncategorized_transactions
+            WHERE ($1::text IS NULL OR transaction_key = $1) AND transaction_type IS NOT NULL
+            ORDER BY id;",
+        )
+        .bind(transaction_key)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list uncategorized transactions")?;
+
+        rows.iter().map(stored_transaction).collect()
+    }
+
+    pub async fn remove_uncategorized_transactions(&mut self, transaction_key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM uncategorized_transactions WHERE transaction_key = $1;")
+            .bind(transaction_key)
+            .execute(&mut *self.conn)
+            .await
+            .wrap_err("Failed to remove uncategorized transaction")?;
+
+        Ok(())
+    }
+
+    /// Find stored transactions, including uncategorized ones when asked
+    pub async fn search_transactions(
+        &mut self,
+        search: &TransactionSearch,
+    ) -> Result<Vec<SearchResult>> {
+        let rows = sqlx::query(
+            "SELECT
+                id,
+                transaction_key,
+                account,
+                posted_date,
+                amount,
+                category,
+                transaction_type,
+                name,
+                memo
+            FROM transactions
+            WHERE ($1::date IS NULL OR posted_date >= $1)
+            AND ($2::date IS NULL OR posted_date < $2)
+            AND (cardinality($3::text[]) = 0 OR account = ANY($3))
+            AND ($4::numeric IS NULL OR abs(amount) >= $4)
+            AND ($5::numeric IS NULL OR abs(amount) <= $5)
+            AND ($6::text IS NULL OR category = $6 OR starts_with(category, $6 || '.'))
+            AND ($7::text IS NULL OR transaction_type = $7 OR user_transaction_type = $7)
+            AND ($8::text IS NULL
+                OR strpos(lower(name), lower($8)) > 0
+                OR strpos(lower(memo), lower($8)) > 0)
+            UNION ALL
+            SELECT
+                id,
+                transaction_key,
+                account,
+                posted_date,
+                amount,
+                NULL,
+                type,
+                name,
+                memo
+            FROM uncategorized_transactions
+            WHERE $9
+            AND ($1::date IS NULL OR posted_date >= $1)
+            AND ($2::date IS NULL OR posted_date < $2)
+            AND (cardinality($3::text[]) = 0 OR account = ANY($3))
+            AND ($4::numeric IS NULL OR abs(amount) >= $4)
+            AND ($5::numeric IS NULL OR abs(amount) <= $5)
+            AND $6::text IS NULL
+            AND ($7::text IS NULL OR type = $7)
+            AND ($8::text IS NULL
+                OR strpos(lower(name), lower($8)) > 0
+                OR strpos(lower(memo), lower($8)) > 0
+                OR strpos(lower(message), lower($8)) > 0);",
+        )
+        .bind(search.filter.from)
+        .bind(search.filter.before)
+        .bind(&search.filter.accounts)
+        .bind(search.min_amount)
+        .bind(search.max_amount)
+        .bind(search.category.as_deref())
+        .bind(search.transaction_type.as_deref())
+        .bind(search.text.as_deref())
+        .bind(search.uncategorized)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to search transactions")?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(SearchResult {
+                    id: row.try_get("id")?,
+                    transaction_key: row.try_get("transaction_key")?,
+                    account: row.try_get("account")?,
+                    posted_date: row.try_get("posted_date")?,
+                    amount: row.try_get("amount")?,
+                    category: row.try_get("category")?,
+                    transaction_type: row.try_get("transaction_type")?,
+                    name: row.try_get("name")?,
+                    memo: row.try_get("memo")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Load the rows of `report_transactions` matching `filter`, ordered by posted date
+    pub async fn list_report_transactions(
+        &mut self,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<ReportTransaction>> {
+        let rows = sqlx::query(
+            "SELECT
+                id,
+                transaction_key,
+                account,
+                base_category,
+                category,
+                source_category,
+                income,
+                transaction_type,
+                user_transaction_type,
+                posted_date,
+                amount,
+                transaction_id,
+                name,
+                memo,
+                tags
+            FROM report_transactions
+            WHERE ($1::date IS NULL OR posted_date >= $1)
+            AND ($2::date IS NULL OR posted_date < $2)
+            AND (cardinality($3::text[]) = 0 OR account = ANY($3))
+            ORDER BY posted_date, transaction_key, category;",
+        )
+        .bind(filter.from)
+        .bind(filter.before)
+        .bind(&filter.accounts)
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list transactions")?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ReportTransaction {
+                    id: row.try_get("id")?,
+                    transaction_key: row.try_get("transaction_key")?,
+                    account: row.try_get("account")?,
+                    base_category: row.try_get("base_category")?,
+                    category: row.try_get("category")?,
+                    source_category: row.try_get("source_category")?,
+                    income: row.try_get("income")?,
+                    transaction_type: row.try_get("transaction_type")?,
+                    user_transaction_type: row.try_get("user_transaction_type")?,
+                    posted_date: row.try_get("posted_date")?,
+                    amount: row.try_get("amount")?,
+                    transaction_id: row.try_get("transaction_id")?,
+                    name: row.try_get("name")?,
+                    memo: row.try_get("memo")?,
+                    tags: row.try_get("tags")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Load the `(category, name)` pair of every stored transaction, and of transactions
+    /// dropped by clean that are no longer stored
+    pub async fn list_categorized_names(&mut self) -> Result<Vec<(String, String)>> {
+        sqlx::query_as(
+            "SELECT category, name FROM transactions
+            UNION ALL
+            SELECT h.category, h.name FROM categorized_history h
+            WHERE NOT EXISTS (
+                SELECT 1 FROM transactions t WHERE t.transaction_key = h.transaction_key
+            );",
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list categorized transactions")
+    }
+
+    /// Load the distinct display names missing a rule, most frequent first, with the
+    /// suggestions made for the latest of them
+    pub async fn list_missing_rules(&mut self) -> Result<Vec<MissingRuleSummary>> {
+        let rows = sqlx::query(
+            "SELECT * FROM (
+                SELECT DISTINCT ON (type, message)
+                    type,
+                    message,
+                    COUNT(*) OVER (PARTITION BY type, message) AS count,
+                    suggestions,
+                    suggestion_confidence
+                FROM uncategorized_transactions
+                WHERE missing_rule
+                ORDER BY type, message, id DESC
+            ) AS missing
+            ORDER BY count DESC, type, message;",
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .wrap_err("Failed to list uncategorized transactions")?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(MissingRuleSummary {
+                    transaction_type: row.try_get("type")?,
+                    display: row.try_get("message")?,
+                    count: row.try_get("count")?,
+                    suggestions: row
+                        .try_get::<Option<Vec<String>>, _>("suggestions")?
+                        .unwrap_or_default(),
+                    suggestion_confidence: row
+                        .try_get::<Option<Vec<f32>>, _>("suggestion_confidence")?
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct MissingRuleSummary {
+    pub transaction_type: String,
+    pub display: String,
+    pub count: i64,
+    pub suggestions: Vec<String>,
+    pub suggestion_confidence: Vec<f32>,
+}
+
+/// A row of the `report_transactions` view. Split transactions have one row per part.
+#[derive(Debug)]
+pub struct ReportTransaction {
+    pub id: i32,
+    pub transaction_key: String,
+    pub account: String,
+    pub base_category: String,
+    pub category: String,
+    pub source_category: Option<String>,
+    pub income: Option<bool>,
+    /// Type given by the source file
+    pub transaction_type: String,
+    /// Type given by the `[[transaction_type]]` config
+    pub user_transaction_type: String,
+    pub posted_date: Option<NaiveDate>,
+    pub amount: Option<Decimal>,
+    pub transaction_id: Option<String>,
+    pub name: String,
+    pub memo: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A transaction found by `DbHandle::search_transactions`
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// Row id, unique across categorized and uncategorized transactions
+    pub id: i32,
+    pub transaction_key: String,
+    pub account: String,
+    pub posted_date: Option<NaiveDate>,
+    pub amount: Option<Decimal>,
+    /// Missing for uncategorized transactions
+    pub category: Option<String>,
+    pub transaction_type: String,
+    pub name: String,
+    pub memo: Option<String>,
+}
+
+/// The first segment of a dotted category path
+fn base_category(category: &str) -> &str {
+    category.split('.').next().unwrap()
 }
@@ -0,0 +1,208 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use serde_json::{Map, Value, json};
+use tokio::io::AsyncWriteExt;
+
+use crate::categories::CategoryTree;
+use crate::config::AccountConfig;
+use crate::db::{Db, ReportTransaction, TransactionFilter};
+use crate::journal::{self, JournalOptions, JournalStyle};
+use crate::output::{self, CsvOutput};
+
+/// File format for `money export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// Ledger and hledger journal
+    Ledger,
+    Beancount,
+}
+
+/// Settings for `export`
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions<'a> {
+    pub format: ExportFormat,
+    /// Include the fields read from the source file
+    pub raw: bool,
+    pub tags: bool,
+    /// Commodity for journal amounts
+    pub currency: Option<&'a str>,
+}
+
+/// An exported field. The names are part of the export format and must not change.
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Id,
+    TransactionKey,
+    Account,
+    PostedDate,
+    Amount,
+    Category,
+    BaseCategory,
+    Income,
+    Name,
+    UserTransactionType,
+    TransactionType,
+    TransactionId,
+    SourceCategory,
+    Memo,
+    Tags,
+}
+
+const COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::TransactionKey,
+    Column::Account,
+    Column::PostedDate,
+    Column::Amount,
+    Column::Category,
+    Column::BaseCategory,
+    Column::Income,
+    Column::Name,
+    Column::UserTransactionType,
+];
+
+/// Fields as they were read from the source file
+const RAW_COLUMNS: &[Column] = &[
+    Column::TransactionType,
+    Column::TransactionId,
+    Column::SourceCategory,
+    Column::Memo,
+];
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::TransactionKey => "transaction_key",
+            Column::Account => "account",
+            Column::PostedDate => "posted_date",
+            Column::Amount => "amount",
+            Column::Category => "category",
+            Column::BaseCategory => "base_category",
+            Column::Income => "income",
+            Column::Name => "name",
+            Column::UserTransactionType => "user_transaction_type",
+            Column::TransactionType => "transaction_type",
+            Column::TransactionId => "transaction_id",
+            Column::SourceCategory => "source_category",
+            Column::Memo => "memo",
+            Column::Tags => "tags",
+        }
+    }
+
+    fn value(self, transaction: &ReportTransaction) -> Value {
+        match self {
+            Column::Id => json!(transaction.id),
+            Column::TransactionKey => json!(transaction.transaction_key),
+            Column::Account => json!(transaction.account),
+            Column::PostedDate => json!(transaction.posted_date),
+            Column::Amount => json!(transaction.amount),
+            Column::Category => json!(transaction.category),
+            Column::BaseCategory => json!(transaction.base_category),
+            Column::Income => json!(transaction.income),
+            Column::Name => json!(transaction.name),
+            Column::UserTransactionType => json!(transaction.user_transaction_type),
+            Column::TransactionType => json!(transaction.transaction_type),
+            Column::TransactionId => json!(transaction.transaction_id),
+            Column::SourceCategory => json!(transaction.source_category),
+            Column::Memo => json!(transaction.memo),
+            Column::Tags => json!(transaction.tags),
+        }
+    }
+}
+
+/// Text for a CSV cell. Lists are joined with `;`.
+fn csv_cell(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        Value::Array(values) => values
+            .into_iter()
+            .map(csv_cell)
+            .collect::<Vec<_>>()
+            .join(";"),
+        other => other.to_string(),
+    }
+}
+
+/// Write the stored transactions matching `filter` to `path`, or stdout without a path.
+/// Split transactions are written once per part in CSV and JSON Lines.
+pub async fn export(
+    db: &Db,
+    accounts: &[AccountConfig],
+    categories: &CategoryTree,
+    filter: &TransactionFilter,
+    options: &ExportOptions<'_>,
+    path: Option<&Path>,
+) -> Result<usize> {
+    let transactions = db
+        .open_handle()
+        .await?
+        .list_report_transactions(filter)
+        .await?;
+
+    let mut columns = COLUMNS.to_vec();
+    if options.raw {
+        columns.extend(RAW_COLUMNS);
+    }
+    if options.tags {
+        columns.push(Column::Tags);
+    }
+
+    let mut writer = output::open(path).await?;
+    match options.format {
+        ExportFormat::Csv => {
+            let mut csv = CsvOutput::from_writer(writer);
+            csv.write(columns.iter().map(|c| c.name())).await?;
+            for transaction in &transactions {
+                csv.write(columns.iter().map(|c| csv_cell(c.value(transaction))))
+                    .await?;
+            }
+            csv.finish().await?;
+        }
+        ExportFormat::Jsonl => {
+            for transaction in &transactions {
+                let object = columns
+                    .iter()
+                    .map(|c| (c.name().to_string(), c.value(transaction)))
+                    .collect::<Map<_, _>>();
+                let mut line = serde_json::to_vec(&object).wrap_err("Failed to serialize JSON")?;
+                line.push(b'\n');
+                writer
+                    .write_all(&line)
+                    .await
+                    .wrap_err("Failed to write export")?;
+            }
+            writer.flush().await.wrap_err("Failed to write export")?;
+        }
+        ExportFormat::Ledger | ExportFormat::Beancount => {
+            let style = if options.format == ExportFormat::Ledger {
+                JournalStyle::Ledger
+            } else {
+                JournalStyle::Beancount
+            };
+            let journal_options = JournalOptions {
+                currency: options.currency,
+                raw: options.raw,
+                tags: options.tags,
+            };
+            let journal =
+                journal::render(style, &transactions, accounts, categories, &journal_options)?;
+
+            writer
+                .write_all(journal.as_bytes())
+                .await
+                .wrap_err("Failed to write export")?;
+            writer.flush().await.wrap_err("Failed to write export")?;
+        }
+    }
+
+    Ok(transactions.len())
+}
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap};
+
+use color_eyre::Result;
+use console::style;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::config::{AppConfig, TransactionTypeMode};
+use crate::db::Db;
+use crate::importer::categorizer::{CategorizationStatus, Categorizer};
+use crate::importer::source::list_accounts;
+use crate::importer::{Transaction, TransactionSink, file_progress, open_file};
+use crate::output::OutputMode;
+
+#[derive(Debug, Default)]
+struct PrefixUsage {
+    /// Number of transactions where this was the longest matching prefix
+    selected: usize,
+    /// Number of transactions where this prefix matched, keyed by the longer prefix that won
+    shadowed_by: HashMap<&'static str, usize>,
+}
+
+#[derive(Debug, Default)]
+struct PatternUsage {
+    /// Number of transactions this rule categorized through the pattern
+    selected: usize,
+    /// Number of transactions the rule's conditions matched, keyed by the rule that won
+    shadowed_by: HashMap<usize, usize>,
+}
+
+/// Tally of which rules and prefixes were hit while categorizing transactions
+pub struct RuleAudit {
+    categorizer: &'static Categorizer,
+    /// `{pattern: usage}` for each rule, in config order
+    rules: Vec<HashMap<&'static str, PatternUsage>>,
+    /// `{account_name: {prefix: usage}}`
+    prefixes: HashMap<&'static str, HashMap<&'static str, PrefixUsage>>,
+    transactions: usize,
+    /// Set when auditing stored transactions, which never include ignored ones
+    stored: bool,
+}
+
+impl RuleAudit {
+    pub fn new(config: &'static AppConfig, categorizer: &'static Categorizer) -> Self {
+        let rules = config
+            .rule
+            .iter()
+            .map(|rule| {
+                rule.patterns
+                    .iter()
+                    .map(|p| (p.as_str(), PatternUsage::default()))
+                    .collect()
+            })
+            .collect();
+
+        let mut prefixes: HashMap<&'static str, HashMap<&'static str, PrefixUsage>> =
+            HashMap::new();
+        for type_config in &config.transaction_type {
+            if type_config.mode != TransactionTypeMode::Prefix {
+                continue;
+            }
+            let Some(prefix) = type_config.prefix.as_deref() else {
+                continue;
+            };
+
+            for account in &type_config.accounts {
+                prefixes
+                    .entry(account.as_str())
+                    .or_default()
+                    .insert(prefix, PrefixUsage::default());
+            }
+        }
+
+        Self {
+            categorizer,
+            rules,
+            prefixes,
+            transactions: 0,
+            stored: false,
+        }
+    }
+
+    fn record(&mut self, account: &str, transaction: &Transaction<'_>) -> Result<()> {
+        self.transactions += 1;
+
+        let matching = self
+            .categorizer
+            .matching_prefixes(account, &transaction.name);
+        if let Some((&longest, shadowed)) = matching.split_last()
+            && let Some(account_prefixes) = self.prefixes.get_mut(account)
+        {
+            if let Some(usage) = account_prefixes.get_mut(longest) {
+                usage.selected += 1;
+            }
+            for prefix in shadowed {
+                if let Some(usage) = account_prefixes.get_mut(prefix) {
+                    *usage.shadowed_by.entry(longest).or_default() += 1;
+                }
+            }
+        }
+
+        let status = self.categorizer.categorize(account, transaction)?;
+        if let CategorizationStatus::Categorized(c) = status {
+            if let Some(usage) = self.rules[c.rule].get_mut(c.pattern) {
+                usage.selected += 1;
+            }
+            for rule in c.shadowed {
+                if let Some(usage) = self.rules[rule].get_mut(c.pattern) {
+                    *usage.shadowed_by.entry(c.rule).or_default() += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn print_report(&self, config: &AppConfig) {
+        println!(
+            "\nAudited {} transactions",
+            style(self.transactions).bold().white()
+        );
+
+        // Rules are numbered from 1 in config order, since several can share a pattern
+        let describe = |index: usize, pattern: &str| {
+            let rule = &config.rule[index];
+            format!(
+                "rule {}: {} {:?} -> {}",
+                index + 1,
+                rule.transaction_type.name(),
+                pattern,
+                rule.category
+            )
+        };
+
+        let mut shadowed_patterns = Vec::new();
+        let mut unused_patterns = Vec::new();
+        for (index, rule) in config.rule.iter().enumerate() {
+            if self.stored && rule.ignore {
+                continue;
+            }
+            for pattern in &rule.patterns {
+                let Some(usage) = self.rules[index].get(pattern.as_str()) else {
+                    continue;
+                };
+                if usage.selected > 0 {
+                    continue;
+                }
+
+                if usage.shadowed_by.is_empty() {
+                    unused_patterns.push(describe(index, pattern));
+                } else {
+                    let shadowed_by: BTreeMap<_, _> = usage.shadowed_by.iter().collect();
+                    let shadows = shadowed_by
+                        .into_iter()
+                        .map(|(&rule, count)| format!("rule {} ({} matches)", rule + 1, count))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    shadowed_patterns.push(format!(
+                        "{} shadowed by {}",
+                        describe(index, pattern),
+                        shadows
+                    ));
+                }
+            }
+        }
+        print_section(
+            "Patterns always shadowed by another rule",
+            &shadowed_patterns,
+        );
+        print_section("Patterns that never matched", &unused_patterns);
+        if self.stored && config.rule.iter().any(|r| r.ignore) {
+            println!(
+                "  {}",
+                style("Ignore rules are left out, since ignored transactions are not stored").dim()
+            );
+        }
+
+        let mut shadowed_prefixes = Vec::new();
+        let mut unused_prefixes = Vec::new();
+        let accounts: BTreeMap<_, _> = self.prefixes.iter().collect();
+        for (account, prefixes) in accounts {
+            let prefixes: BTreeMap<_, _> = prefixes.iter().collect();
+            for (prefix, usage) in prefixes {
+                if usage.selected > 0 {
+                    continue;
+                }
+
+                if usage.shadowed_by.is_empty() {
+                    unused_prefixes.push(format!("{}: {:?}", account, prefix));
+                } else {
+                    let shadowed_by: BTreeMap<_, _> = usage.shadowed_by.iter().collect();
+                    let shadows = shadowed_by
+                        .into_iter()
+                        .map(|(p, count)| format!("{:?} ({} matches)", p, count))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    shadowed_prefixes
+                        .push(format!("{}: {:?} shadowed by {}", account, prefix, shadows));
+                }
+            }
+        }
+        print_section(
+            "Prefixes always shadowed by a longer prefix",
+            &shadowed_prefixes,
+        );
+        print_section("Prefixes that never matched", &unused_prefixes);
+
+        let mut types_without_rules = Vec::new();
+        for type_config in &config.transaction_type {
+            let has_rules = config.rule.iter().any(|r| {
+                r.transaction_type == type_config.transaction_type && !r.patterns.is_empty()
+            });
+            let name = type_config.transaction_type.name().to_string();
+            if !has_rules && !types_without_rules.contains(&name) {
+                types_without_rules.push(name);
+            }
+        }
+        print_section("Transaction types without rules", &types_without_rules);
+
+        let mut category_patterns: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for rule in &config.rule {
+            category_patterns
+                .entry(rule.category.as_str())
+                .or_default()
+                .extend(rule.patterns.iter().map(|p| p.as_str()));
+        }
+        let single_pattern_categories = category_patterns
+            .into_iter()
+            .filter(|(_, patterns)| patterns.len() == 1)
+            .map(|(category, patterns)| format!("{} ({:?})", category, patterns[0]))
+            .collect::<Vec<_>>();
+        print_section(
+            "Categories used by a single pattern",
+            &single_pattern_categories,
+        );
+    }
+}
+
+fn print_section(title: &str, lines: &[String]) {
+    println!("\n{} ({})", style(title).bold().white(), lines.len());
+    for line in lines {
+        println!("  {}", line);
+    }
+}
+
+struct AuditSink<'a> {
+    audit: &'a mut RuleAudit,
+    account_name: String,
+}
+
+impl TransactionSink for AuditSink<'_> {
+    async fn import(&mut self, transaction: Transaction<'_>) -> Result<()> {
+        self.audit.record(&self.account_name, &transaction)
+    }
+}
+
+/// Run every transaction in the account source directories through the audit
+pub async fn audit_files(
+    audit: &mut RuleAudit,
+    config: &AppConfig,
+    output: OutputMode,
+) -> Result<()> {
+    let (file_tx, file_rx) = tokio::sync::mpsc::channel(8);
+    let (multi_progress, list_progress) = file_progress(output);
+
+    let account_listing = list_accounts(&config.account, file_tx, &list_progress, false);
+    let file_loading = async {
+        let mut files = ReceiverStream::new(file_rx);
+        while let Some(file) = files.next().await {
+            let Some((format, progress)) =
+                open_file(&file.path, file.format, &multi_progress, &list_progress).await?
+            else {
+                continue;
+            };
+
+            let sink = AuditSink {
+                audit: &mut *audit,
+                account_name: file.account_name,
+            };
+            format.load(&file.path, sink, &progress).await?;
+            list_progress.inc(1);
+            multi_progress.remove(&progress);
+        }
+
+        Ok(())
+    };
+
+    futures::future::try_join(account_listing, file_loading).await?;
+    list_progress.finish_and_clear();
+
+    Ok(())
+}
+
+/// Run every transaction already stored in the database through the audit,
+/// both categorized and uncategorized
+pub async fn audit_stored(audit: &mut RuleAudit, db: &Db) -> Result<()> {
+    audit.stored = true;
+
+    let mut conn = db.open_handle().await?;
+    let categorized = conn.list_transactions(None).await?;
+    let uncategorized = conn.list_uncategorized_transactions(None).await?;
+    for (account_name, transaction) in categorized.into_iter().chain(uncategorized) {
+        audit.record(&account_name, &transaction)?;
+    }
+
+    Ok(())
+}
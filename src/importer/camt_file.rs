@@ -0,0 +1,374 @@
+// ISO 20022 camt.053 statements and camt.052 account reports, as exported by European banks
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use color_eyre::Result;
+use color_eyre::eyre::{Context, OptionExt, bail, eyre};
+use indicatif::ProgressBar;
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use rust_decimal::Decimal;
+
+use crate::importer::format;
+use crate::importer::{
+    BalanceKind, StatementBalance, Transaction, TransactionReader, TransactionSink, TransactionType,
+};
+
+#[derive(Debug, Default)]
+struct CamtEntry {
+    reference: Option<String>,
+    servicer_reference: Option<String>,
+    amount: Option<Decimal>,
+    credit: Option<bool>,
+    status: Option<String>,
+    booking_date: Option<NaiveDate>,
+    creditor: Option<String>,
+    debtor: Option<String>,
+    remittance: Vec<String>,
+    additional_info: Option<String>,
+}
+
+impl CamtEntry {
+    /// Pending and informational entries show up again once they are booked
+    fn is_booked(&self) -> bool {
+        self.status.as_deref().is_none_or(|s| s == "BOOK")
+    }
+
+    fn into_transaction(self) -> Result<Transaction<'static>> {
+        let amount = self.amount.ok_or_eyre("Entry missing an amount")?;
+        let credit = self
+            .credit
+            .ok_or_eyre("Entry missing a credit/debit indicator")?;
+        let date_posted = self
+            .booking_date
+            .ok_or_eyre("Entry missing a booking date")?;
+
+        // The other party is the one receiving a debit, or sending a credit
+        let (counterparty, other) = if credit {
+            (self.debtor, self.creditor)
+        } else {
+            (self.creditor, self.debtor)
+        };
+        let memo = Some(self.remittance.join(" ")).filter(|m| !m.is_empty());
+        let name = counterparty
+            .or(other)
+            .or(self.additional_info)
+            .or_else(|| memo.clone())
+            .unwrap_or_default();
+
+        Ok(Transaction {
+            transaction_type: if credit {
+                TransactionType::Credit
+            } else {
+                TransactionType::Debit
+            },
+            date_posted,
+            amount: if credit { amount } else { -amount },
+            transaction_id: self.reference.or(self.servicer_reference).map(Cow::Owned),
+            category: None,
+            name: Cow::Owned(name),
+            memo: memo.map(Cow::Owned),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct CamtBalance {
+    code: Option<String>,
+    amount: Option<Decimal>,
+    credit: Option<bool>,
+    date: Option<NaiveDate>,
+}
+
+impl CamtBalance {
+    /// Convert booked opening and closing balances. Other balance types are skipped.
+    fn into_balance(self) -> Result<Option<StatementBalance>> {
+        let kind = match self.code.as_deref() {
+            // Opening booked, or closing booked of the previous statement
+            Some("OPBD" | "PRCD") => BalanceKind::Opening,
+            // Closing booked, or interim booked at the end of a camt.052 report
+            Some("CLBD" | "ITBD") => BalanceKind::Closing,
+            _ => return Ok(None),
+        };
+
+        let amount = self.amount.ok_or_eyre("Balance missing an amount")?;
+        let credit = self
+            .credit
+            .ok_or_eyre("Balance missing a credit/debit indicator")?;
+
+        Ok(Some(StatementBalance {
+            kind,
+            date: self.date.ok_or_eyre("Balance missing a date")?,
+            amount: if credit { amount } else { -amount },
+        }))
+    }
+}
+
+pub struct CamtReader {
+    contents: Vec<u8>,
+}
+
+impl TransactionReader for CamtReader {
+    const NAME: &str = "camt";
+    const DESCRIPTION: &str = "ISO 20022 camt.053 statements and camt.052 account reports";
+    const EXTENSIONS: &[&str] = &["xml"];
+
+    /// Whether the start of a file is XML with a camt namespace or document element
+    fn sniff(head: &[u8]) -> bool {
+        head.starts_with(b"<")
+            && (format::contains(head, b"camt.05") || format::contains(head, b"<BkToCstmr"))
+    }
+
+    async fn open(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read(path)
+            .await
+            .wrap_err("Failed to open file")?;
+
+        Ok(Self { contents })
+    }
+
+    async fn load(self, mut sink: impl TransactionSink, progress: &ProgressBar) -> Result<()> {
+        // Text is trimmed once the element ends, since entity references split it into pieces
+        let mut reader = Reader::from_reader(&self.contents[..]);
+
+        let mut buf = Vec::new();
+        // Local names of the open elements
+        let mut path: Vec<String> = Vec::new();
+        let mut text = String::new();
+        let mut entry: Option<(usize, CamtEntry)> = None;
+        let mut balance: Option<(usize, CamtBalance)> = None;
+
+        let mut i = 0usize;
+        loop {
+            let event = reader
+                .read_event_into(&mut buf)
+                .wrap_err_with(|| format!("Invalid XML at byte {}", reader.buffer_position()))?;
+
+            match event {
+                Event::Start(e) => {
+                    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                    if path.len() == 1
+                        && !matches!(name.as_str(), "BkToCstmrStmt" | "BkToCstmrAcctRpt")
+                    {
+                        bail!("Not a camt.053 statement or camt.052 report");
+                    }
+
+                    match name.as_str() {
+                        "Ntry" => entry = Some((path.len(), CamtEntry::default())),
+                        "Bal" if entry.is_none() => {
+                            balance = Some((path.len(), CamtBalance::default()))
+                        }
+                        _ => {}
+                    }
+                    path.push(name);
+                    text.clear();
+                }
+                Event::Text(e) => text.push_str(&e.xml_content()?),
+                Event::CData(e) => text.push_str(&e.decode()?),
+                Event::GeneralRef(e) => match e.resolve_char_ref()? {
+                    Some(c) => text.push(c),
+                    None => {
+                        let name = e.decode()?;
+                        let value = resolve_predefined_entity(&name)
+                            .ok_or_else(|| eyre!("Unknown XML entity &{};", name))?;
+                        text.push_str(value);
+                    }
+                },
+                Event::End(_) => {
+                    let value = text.trim();
+
+                    if let Some((depth, current)) = &mut entry {
+                        if path.len() == *depth + 1 {
+                            let (_, finished) = entry.take().unwrap();
+                            if finished.is_booked() {
+                                let transaction = finished
+                                    .into_transaction()
+                                    .wrap_err_with(|| format!("Invalid entry {}", i + 1))?;
+                                sink.import(transaction).await?;
+
+                                if i.is_multiple_of(100) {
+                                    progress.inc(100);
+                                }
+                                i += 1;
+                            }
+                        } else {
+                            let relative = path[*depth + 1..]
+                                .iter()
+                                .map(String::as_str)
+                                .collect::<Vec<_>>();
+                            read_entry_field(current, &relative, value)?;
+                        }
+                    } else if let Some((depth, current)) = &mut balance {
+                        if path.len() == *depth + 1 {
+                            let (_, finished) = balance.take().unwrap();
+                            if let Some(b) = finished.into_balance()? {
+                                sink.balance(b).await?;
+                            }
+                        } else {
+                            let relative = path[*depth + 1..]
+                                .iter()
+                                .map(String::as_str)
+                                .collect::<Vec<_>>();
+                            read_balance_field(current, &relative, value)?;
+                        }
+                    }
+
+                    path.pop();
+                    text.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(())
+    }
+}
+
+fn read_entry_field(entry: &mut CamtEntry, path: &[&str], value: &str) -> Result<()> {
+    let value = value.to_string();
+    match path {
+        ["NtryRef"] => entry.reference = Some(value),
+        ["AcctSvcrRef"] => entry.servicer_reference = Some(value),
+        ["Amt"] => entry.amount = Some(parse_amount(&value)?),
+        ["CdtDbtInd"] => entry.credit = Some(parse_indicator(&value)?),
+        // Older versions hold the code directly, newer ones nest it
+        ["Sts"] | ["Sts", "Cd"] => entry.status = Some(value),
+        ["BookgDt", "Dt" | "DtTm"] => entry.booking_date = Some(parse_date(&value)?),
+        [.., "RltdPties", "Cdtr", "Nm"] | [.., "RltdPties", "Cdtr", "Pty", "Nm"] => {
+            entry.creditor.get_or_insert(value);
+        }
+        [.., "RltdPties", "Dbtr", "Nm"] | [.., "RltdPties", "Dbtr", "Pty", "Nm"] => {
+            entry.debtor.get_or_insert(value);
+        }
+        [.., "RmtInf", "Ustrd"] | [.., "RmtInf", "Strd", "CdtrRefInf", "Ref"] => {
+            entry.remittance.push(value)
+        }
+        ["AddtlNtryInf"] => entry.additional_info = Some(value),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn read_balance_field(balance: &mut CamtBalance, path: &[&str], value: &str) -> Result<()> {
+    match path {
+        ["Tp", "CdOrPrtry", "Cd"] => balance.code = Some(value.to_string()),
+        ["Amt"] => balance.amount = Some(parse_amount(value)?),
+        ["CdtDbtInd"] => balance.credit = Some(parse_indicator(value)?),
+        ["Dt", "Dt" | "DtTm"] => balance.date = Some(parse_date(value)?),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_amount(value: &str) -> Result<Decimal> {
+    Decimal::from_str_exact(value).wrap_err_with(|| format!("Invalid amount {:?}", value))
+}
+
+/// Whether a `CdtDbtInd` is a credit
+fn parse_indicator(value: &str) -> Result<bool> {
+    match value {
+        "CRDT" => Ok(true),
+        "DBIT" => Ok(false),
+        _ => bail!("Invalid credit/debit indicator {:?}", value),
+    }
+}
+
+/// Parse an ISO date, or the date part of an ISO date time
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    value
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .ok_or_else(|| eyre!("Invalid date {:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&[&str], &str)]) -> CamtEntry {
+        let mut entry = CamtEntry::default();
+        for (path, value) in fields {
+            read_entry_field(&mut entry, path, value).unwrap();
+        }
+        entry
+    }
+
+    #[test]
+    fn debit_names_the_creditor() {
+        let transaction = entry(&[
+            (&["NtryRef"], "REF1"),
+            (&["AcctSvcrRef"], "SVC1"),
+            (&["Amt"], "12.50"),
+            (&["CdtDbtInd"], "DBIT"),
+            (&["BookgDt", "Dt"], "2025-03-04"),
+            (&["NtryDtls", "TxDtls", "RltdPties", "Cdtr", "Nm"], "Shop"),
+            (&["NtryDtls", "TxDtls", "RltdPties", "Dbtr", "Nm"], "Me"),
+            (&["NtryDtls", "TxDtls", "RmtInf", "Ustrd"], "Invoice"),
+            (&["NtryDtls", "TxDtls", "RmtInf", "Ustrd"], "42"),
+        ])
+        .into_transaction()
+        .unwrap();
+
+        assert_eq!(transaction.transaction_type, TransactionType::Debit);
+        assert_eq!(transaction.amount, Decimal::new(-1250, 2));
+        assert_eq!(
+            transaction.date_posted,
+            NaiveDate::from_ymd_opt(2025, 3, 4).unwrap()
+        );
+        assert_eq!(transaction.transaction_id.as_deref(), Some("REF1"));
+        assert_eq!(transaction.name, "Shop");
+        assert_eq!(transaction.memo.as_deref(), Some("Invoice 42"));
+    }
+
+    #[test]
+    fn credit_falls_back_to_other_names() {
+        let transaction = entry(&[
+            (&["AcctSvcrRef"], "SVC1"),
+            (&["Amt"], "100"),
+            (&["CdtDbtInd"], "CRDT"),
+            (&["BookgDt", "DtTm"], "2025-03-04T10:00:00"),
+            (&["AddtlNtryInf"], "Interest"),
+        ])
+        .into_transaction()
+        .unwrap();
+
+        assert_eq!(transaction.transaction_type, TransactionType::Credit);
+        assert_eq!(transaction.amount, Decimal::new(100, 0));
+        assert_eq!(transaction.transaction_id.as_deref(), Some("SVC1"));
+        assert_eq!(transaction.name, "Interest");
+        assert_eq!(transaction.memo, None);
+    }
+
+    #[test]
+    fn only_booked_entries() {
+        assert!(entry(&[]).is_booked());
+        assert!(entry(&[(&["Sts", "Cd"], "BOOK")]).is_booked());
+        assert!(!entry(&[(&["Sts"], "PDNG")]).is_booked());
+    }
+
+    #[test]
+    fn balance_kinds() {
+        let balance = |code: &str| CamtBalance {
+            code: Some(code.to_string()),
+            amount: Some(Decimal::new(5, 0)),
+            credit: Some(false),
+            date: NaiveDate::from_ymd_opt(2025, 3, 31),
+        };
+
+        let closing = balance("CLBD").into_balance().unwrap().unwrap();
+        assert_eq!(closing.kind, BalanceKind::Closing);
+        assert_eq!(closing.amount, Decimal::new(-5, 0));
+        assert_eq!(
+            balance("PRCD").into_balance().unwrap().unwrap().kind,
+            BalanceKind::Opening
+        );
+        assert!(balance("CLAV").into_balance().unwrap().is_none());
+    }
+}
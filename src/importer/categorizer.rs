@@ -1,29 +1,38 @@
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 
 use color_eyre::Result;
 use color_eyre::eyre::{OptionExt, bail};
 use patricia_tree::GenericPatriciaMap;
 
+use crate::categories::CategoryTree;
 use crate::config::{
-    IncomeType, NameSource, TransactionRuleConfig, TransactionTypeConfig, TransactionTypeMode,
-    UserTransactionType,
+    AmountSign, IncomeType, NameSource, RuleConditionConfig, TransactionRuleConfig,
+    TransactionTypeConfig, TransactionTypeMode, UserTransactionType,
 };
-use crate::importer::TransactionType;
+use crate::importer::{Transaction, TransactionType};
 
 #[derive(Debug, Clone)]
 struct TransactionDecoder {
     transaction_type: UserTransactionType,
+    prefix: Option<&'static str>,
     name_source: NameSource,
     income: IncomeType,
-    categories: HashMap<&'static str, PatternCategory>,
+    categories: HashMap<&'static str, PatternRules>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Categorization {
+    pub transaction_type: UserTransactionType,
+    /// The rule pattern that matched the display name
+    pub pattern: &'static str,
+    /// Index of the selected rule in the config
+    pub rule: usize,
+    /// Later rules for the same pattern whose conditions also matched
+    pub shadowed: Vec<usize>,
     pub income: IncomeType,
     pub ignore: bool,
     pub category: &'static str,
+    pub tags: &'static [String],
 }
 
 #[derive(Debug)]
@@ -36,6 +45,7 @@ pub enum UncategorizedTransaction {
     MissingRule {
         account: String,
         transaction_type: UserTransactionType,
+        income: IncomeType,
         display: String,
     },
 }
@@ -57,42 +67,229 @@ pub struct Categorizer {
 
 #[derive(Debug, Clone)]
 struct PatternCategory {
+    rule: usize,
     category: &'static str,
     ignore: bool,
+    tags: &'static [String],
+}
+
+#[derive(Debug, Clone, Default)]
+struct PatternRules {
+    /// Rules with conditions, checked in config order before the plain rule
+    conditional: Vec<(&'static RuleConditionConfig, PatternCategory)>,
+    plain: Option<PatternCategory>,
+}
+
+impl PatternRules {
+    /// Rules that apply to the transaction, the selected one first
+    fn matching<'r>(
+        &'r self,
+        account: &str,
+        transaction: &Transaction<'_>,
+    ) -> impl Iterator<Item = &'r PatternCategory> {
+        self.conditional
+            .iter()
+            .filter(move |(condition, _)| condition_matches(condition, account, transaction))
+            .map(|(_, category)| category)
+            .chain(&self.plain)
+    }
+}
+
+fn condition_matches(
+    condition: &RuleConditionConfig,
+    account: &str,
+    transaction: &Transaction<'_>,
+) -> bool {
+    let amount = transaction.amount.abs();
+    if condition.min_amount.is_some_and(|min| amount < min)
+        || condition.max_amount.is_some_and(|max| amount > max)
+    {
+        return false;
+    }
+
+    match condition.sign {
+        Some(AmountSign::Positive) if !transaction.amount.is_sign_positive() => return false,
+        Some(AmountSign::Negative) if !transaction.amount.is_sign_negative() => return false,
+        _ => {}
+    }
+
+    if condition
+        .from
+        .is_some_and(|from| transaction.date_posted < from)
+        || condition
+            .before
+            .is_some_and(|before| transaction.date_posted >= before)
+    {
+        return false;
+    }
+
+    if let Some(memo_match) = &condition.memo {
+        let Some(memo) = &transaction.memo else {
+            return false;
+        };
+        if !memo.to_lowercase().contains(&memo_match.to_lowercase()) {
+            return false;
+        }
+    }
+
+    if let Some(accounts) = &condition.accounts
+        && !accounts.iter().any(|a| a == account)
+    {
+        return false;
+    }
+
+    true
+}
+
+/// Describe why a conditional rule can never match, if it can't
+fn unreachable_reason(
+    rule: &TransactionRuleConfig,
+    type_accounts: &HashSet<&str>,
+) -> Option<String> {
+    let condition = &rule.when;
+
+    if let (Some(min), Some(max)) = (condition.min_amount, condition.max_amount)
+        && min > max
+    {
+        return Some(format!("min_amount {} is above max_amount {}", min, max));
+    }
+
+    if let (Some(from), Some(before)) = (condition.from, condition.before)
+        && from >= before
+    {
+        return Some(format!("from {} is not before {}", from, before));
+    }
+
+    if let Some(accounts) = &condition.accounts {
+        if accounts.is_empty() {
+            return Some("accounts is empty".to_string());
+        }
+
+        let missing = accounts
+            .iter()
+            .filter(|a| !type_accounts.contains(a.as_str()))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Some(format!(
+                "transaction type {} is not used by accounts {:?}",
+                rule.transaction_type.name(),
+                missing
+            ));
+        }
+    }
+
+    None
 }
 
 impl Categorizer {
     pub fn build(
         transaction_types: &'static [TransactionTypeConfig],
         rules: &'static [TransactionRuleConfig],
+        categories: &CategoryTree,
     ) -> Result<Self> {
-        let mut type_categories: HashMap<
-            UserTransactionType,
-            HashMap<&'static str, PatternCategory>,
-        > = HashMap::new();
-        for rule in rules {
+        let undeclared = rules
+            .iter()
+            .filter(|r| !categories.accepts(&r.category))
+            .map(|r| {
+                format!(
+                    "{} rule for {:?} -> {}",
+                    r.transaction_type.name(),
+                    r.patterns,
+                    r.category
+                )
+            })
+            .collect::<Vec<_>>();
+        if !undeclared.is_empty() {
+            bail!(
+                "Rules use undeclared categories:\n  {}",
+                undeclared.join("\n  ")
+            );
+        }
+
+        let mut type_accounts: HashMap<UserTransactionType, HashSet<&str>> = HashMap::new();
+        for type_config in transaction_types {
+            type_accounts
+                .entry(type_config.transaction_type)
+                .or_default()
+                .extend(type_config.accounts.iter().map(|a| a.as_str()));
+        }
+
+        let mut unreachable = Vec::new();
+        let mut type_categories: HashMap<UserTransactionType, HashMap<&'static str, PatternRules>> =
+            HashMap::new();
+        for (index, rule) in rules.iter().enumerate() {
             let entry = type_categories.entry(rule.transaction_type).or_default();
+            let pattern_category = PatternCategory {
+                rule: index,
+                category: rule.category.as_str(),
+                ignore: rule.ignore,
+                tags: &rule.tags,
+            };
+
+            if !rule.when.is_empty()
+                && let Some(reason) = unreachable_reason(
+                    rule,
+                    type_accounts
+                        .get(&rule.transaction_type)
+                        .unwrap_or(&HashSet::new()),
+                )
+            {
+                unreachable.push(format!(
+                    "{} rule for {:?} -> {}: {}",
+                    rule.transaction_type.name(),
+                    rule.patterns,
+                    rule.category,
+                    reason
+                ));
+                continue;
+            }
 
             for pattern_str in &rule.patterns {
-                match entry.entry(pattern_str.as_str()) {
-                    Entry::Occupied(e) => {
+                let pattern_rules = entry.entry(pattern_str.as_str()).or_default();
+
+                if !rule.when.is_empty() {
+                    if let Some((_, earlier)) = pattern_rules
+                        .conditional
+                        .iter()
+                        .find(|(condition, _)| **condition == rule.when)
+                    {
+                        unreachable.push(format!(
+                            "{} rule for {:?} -> {}: same conditions as earlier rule -> {}",
+                            rule.transaction_type.name(),
+                            pattern_str,
+                            rule.category,
+                            earlier.category
+                        ));
+                        continue;
+                    }
+
+                    pattern_rules
+                        .conditional
+                        .push((&rule.when, pattern_category.clone()));
+                    continue;
+                }
+
+                match &mut pattern_rules.plain {
+                    Some(existing) => {
                         bail!(
                             "Duplicate rule for pattern {:?}. Old category: {:?}, new category: {:?}",
-                            e.key(),
-                            e.get(),
+                            pattern_str,
+                            existing,
                             &rule.category
                         );
                     }
-                    Entry::Vacant(e) => {
-                        e.insert(PatternCategory {
-                            category: rule.category.as_str(),
-                            ignore: rule.ignore,
-                        });
-                    }
+                    plain => *plain = Some(pattern_category.clone()),
                 }
             }
         }
 
+        if !unreachable.is_empty() {
+            bail!(
+                "Rules that can never match:\n  {}",
+                unreachable.join("\n  ")
+            );
+        }
+
         let mut prefix_map = HashMap::new();
         let mut source_type_map = HashMap::new();
         for type_config in transaction_types {
@@ -103,6 +300,7 @@ impl Categorizer {
 
             let decoder = TransactionDecoder {
                 transaction_type: type_config.transaction_type,
+                prefix: type_config.prefix.as_deref(),
                 name_source: type_config.name_source,
                 income: type_config.income,
                 categories,
@@ -156,10 +354,12 @@ impl Categorizer {
     pub fn categorize(
         &self,
         account: &str,
-        name: &str,
-        transaction_tye: TransactionType,
-        memo: Option<&str>,
+        transaction: &Transaction<'_>,
     ) -> Result<CategorizationStatus> {
+        let name = transaction.name.as_ref();
+        let transaction_tye = transaction.transaction_type;
+        let memo = transaction.memo.as_deref();
+
         let prefix_match = self
             .prefix_map
             .get(account)
@@ -203,20 +403,51 @@ impl Categorizer {
         };
         display_name = display_name.trim();
 
-        let Some(category) = decoder.categories.get(display_name) else {
+        let mut matching = decoder
+            .categories
+            .get_key_value(display_name)
+            .map(|(p, rules)| (p, rules.matching(account, transaction)));
+        let Some((pattern, category)) = matching
+            .as_mut()
+            .and_then(|(p, rules)| Some((*p, rules.next()?)))
+        else {
             return Ok(CategorizationStatus::Uncategorized(
                 UncategorizedTransaction::MissingRule {
                     account: account.to_string(),
                     transaction_type: decoder.transaction_type,
+                    income: decoder.income,
                     display: display_name.to_string(),
                 },
             ));
         };
 
         Ok(CategorizationStatus::Categorized(Categorization {
+            transaction_type: decoder.transaction_type,
+            pattern,
+            rule: category.rule,
+            shadowed: matching
+                .into_iter()
+                .flat_map(|(_, rules)| rules)
+                .map(|c| c.rule)
+                .collect(),
             income: decoder.income,
             ignore: category.ignore,
             category: category.category,
+            tags: category.tags,
         }))
     }
+
+    /// List every prefix configured for `account` that `name` starts with, shortest first.
+    /// The last entry is the prefix `categorize` would select.
+    pub fn matching_prefixes(&self, account: &str, name: &str) -> Vec<&'static str> {
+        self.prefix_map
+            .get(account)
+            .map(|prefixes| {
+                prefixes
+                    .common_prefix_values(name)
+                    .filter_map(|d| d.prefix)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
@@ -13,7 +13,7 @@ use rust_decimal::Decimal;
 use tokio::fs::File;
 use tokio::io::BufReader;
 
-use crate::importer::{Transaction, TransactionImporter, TransactionReader, TransactionType};
+use crate::importer::{Transaction, TransactionReader, TransactionSink, TransactionType};
 
 struct CsvTransaction {
     posted_date: NaiveDate,
@@ -46,13 +46,54 @@ impl<'a> CsvTransaction {
     }
 }
 
+/// Header row of a bank's CSV export
+struct CsvProfile {
+    /// Columns the reader needs
+    required: &'static [&'static str],
+    /// Columns that may also appear
+    optional: &'static [&'static str],
+}
+
+impl CsvProfile {
+    fn matches(&self, headers: &[&str]) -> bool {
+        self.required.iter().all(|r| headers.contains(r))
+            && headers
+                .iter()
+                .all(|h| self.required.contains(h) || self.optional.contains(h))
+    }
+}
+
+const PROFILES: &[CsvProfile] = &[
+    // Capital One
+    CsvProfile {
+        required: &["Posted Date", "Description", "Category", "Debit", "Credit"],
+        optional: &["Transaction Date", "Card No."],
+    },
+];
+
 pub struct CsvReader {
     reader: AsyncReader<BufReader<File>>,
     columns: ColumnMap,
 }
 
-impl CsvReader {
-    pub async fn open(path: &Path) -> Result<Self> {
+impl TransactionReader for CsvReader {
+    const NAME: &str = "csv";
+    const DESCRIPTION: &str = "CSV exports with a known header row (Capital One)";
+    const EXTENSIONS: &[&str] = &["csv"];
+
+    /// Whether the header row matches a known bank's columns
+    fn sniff(head: &[u8]) -> bool {
+        let first_line = head.split(|b| *b == b'\n').next().unwrap_or_default();
+        let first_line = String::from_utf8_lossy(first_line);
+        let headers = first_line
+            .split(',')
+            .map(|h| h.trim().trim_matches('"'))
+            .collect::<Vec<_>>();
+
+        PROFILES.iter().any(|p| p.matches(&headers))
+    }
+
+    async fn open(path: &Path) -> Result<Self> {
         let mut reader = AsyncReader::from_reader(BufReader::new(
             File::open(path).await.wrap_err("Failed to open file")?,
         ));
@@ -125,14 +166,8 @@ impl CsvReader {
 
         Ok(Self { reader, columns })
     }
-}
 
-impl TransactionReader for CsvReader {
-    async fn load(
-        self,
-        mut importer: TransactionImporter<'_>,
-        progress: &ProgressBar,
-    ) -> Result<()> {
+    async fn load(self, mut sink: impl TransactionSink, progress: &ProgressBar) -> Result<()> {
         let mut records = self.reader.into_records();
 
         let mut i = 0usize;
@@ -144,9 +179,9 @@ impl TransactionReader for CsvReader {
                 .and_then(|t| t.into_transaction())
                 .wrap_err("Failed to convert CsvTransaction")?;
 
-            importer.import(transaction).await?;
+            sink.import(transaction).await?;
 
-            if i % 100 == 0 {
+            if i.is_multiple_of(100) {
                 progress.inc(100);
             }
 
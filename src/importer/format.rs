@@ -0,0 +1,159 @@
+// Registry of statement formats, and picking one from a file's contents with the extension
+// only as a hint
+
+use std::path::Path;
+
+use color_eyre::Result;
+use color_eyre::eyre::{Context, eyre};
+use indicatif::ProgressBar;
+use tokio::io::AsyncReadExt;
+
+use crate::config::AccountConfig;
+use crate::importer::camt_file::CamtReader;
+use crate::importer::csv_file::CsvReader;
+use crate::importer::mt940_file::Mt940Reader;
+use crate::importer::qfx_file::QfxReader;
+use crate::importer::qif_file::QifReader;
+use crate::importer::{TransactionReader, TransactionSink};
+
+/// Bytes read from the start of a file to detect its format
+const HEAD_LEN: u64 = 4096;
+
+/// Declare `FileFormat` with one variant per reader, forwarding to its `TransactionReader` impl
+macro_rules! formats {
+    ($($variant:ident => $reader:ty),+ $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum FileFormat {
+            $($variant),+
+        }
+
+        impl FileFormat {
+            /// Every format, in detection order
+            pub const ALL: &[FileFormat] = &[$(FileFormat::$variant),+];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => <$reader>::NAME),+
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(Self::$variant => <$reader>::DESCRIPTION),+
+                }
+            }
+
+            pub fn extensions(self) -> &'static [&'static str] {
+                match self {
+                    $(Self::$variant => <$reader>::EXTENSIONS),+
+                }
+            }
+
+            fn sniff(self, head: &[u8]) -> bool {
+                match self {
+                    $(Self::$variant => <$reader>::sniff(head)),+
+                }
+            }
+
+            /// Read every transaction in `path` into `sink`
+            pub async fn load(
+                self,
+                path: &Path,
+                sink: impl TransactionSink,
+                progress: &ProgressBar,
+            ) -> Result<()> {
+                let open_error = || format!("Failed to open file: {}", path.to_string_lossy());
+                match self {
+                    $(Self::$variant => {
+                        <$reader>::open(path)
+                            .await
+                            .wrap_err_with(open_error)?
+                            .load(sink, progress)
+                            .await
+                    })+
+                }
+            }
+        }
+    };
+}
+
+// Ordered from the most to the least distinctive signature
+formats! {
+    Qfx => QfxReader,
+    Camt => CamtReader,
+    Qif => QifReader,
+    Mt940 => Mt940Reader,
+    Csv => CsvReader,
+}
+
+impl FileFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    /// The format an account pins in its config
+    pub fn pinned(account: &AccountConfig) -> Result<Option<Self>> {
+        account
+            .format
+            .as_deref()
+            .map(|name| {
+                Self::from_name(name).ok_or_else(|| {
+                    eyre!(
+                        "Account {:?} has unknown format {:?}, see `money formats`",
+                        account.name,
+                        name
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.extensions().contains(&ext.as_str()))
+    }
+
+    /// Detect the format of a file from its contents. The format its extension suggests is
+    /// tried first, but only used if the contents match.
+    pub async fn detect(path: &Path) -> Result<Option<Self>> {
+        let head = read_head(path).await?;
+        let head = trim_head(&head);
+        let hint = path
+            .extension()
+            .and_then(|ext| Self::from_extension(&ext.to_string_lossy()));
+
+        Ok(hint
+            .into_iter()
+            .chain(Self::ALL.iter().copied())
+            .find(|format| format.sniff(head)))
+    }
+}
+
+/// Read the first few kilobytes of a file
+async fn read_head(path: &Path) -> Result<Vec<u8>> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .wrap_err_with(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+
+    let mut head = Vec::new();
+    file.take(HEAD_LEN)
+        .read_to_end(&mut head)
+        .await
+        .wrap_err_with(|| format!("Failed to read file: {}", path.to_string_lossy()))?;
+    Ok(head)
+}
+
+/// Skip a UTF-8 byte order mark and leading whitespace
+fn trim_head(head: &[u8]) -> &[u8] {
+    head.strip_prefix(b"\xEF\xBB\xBF")
+        .unwrap_or(head)
+        .trim_ascii_start()
+}
+
+/// Whether `needle` appears anywhere in `head`
+pub(super) fn contains(head: &[u8], needle: &[u8]) -> bool {
+    head.windows(needle.len()).any(|window| window == needle)
+}
@@ -1,27 +1,40 @@
+pub mod audit;
+mod camt_file;
 pub mod categorizer;
 mod csv_file;
+pub mod format;
+mod mt940_file;
 mod qfx_file;
+mod qif_file;
+pub mod source;
+pub mod suggester;
 
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 use categorizer::Categorizer;
 use chrono::NaiveDate;
-use color_eyre::eyre::{Context, Result, eyre};
+use color_eyre::eyre::{Context, Result, bail, eyre};
 use console::Emoji;
-use csv_file::CsvReader;
 use futures::{StreamExt, TryStreamExt};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::Sender;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
 use tokio_stream::wrappers::ReceiverStream;
 
 use crate::config::AccountConfig;
-use crate::db::{Db, DbHandle};
-use crate::importer::categorizer::CategorizationStatus;
-use crate::importer::qfx_file::QfxReader;
+use crate::db::{AssignedCategory, Db, DbHandle};
+use crate::importer::categorizer::{CategorizationStatus, UncategorizedTransaction};
+use crate::importer::format::FileFormat;
+use crate::importer::source::{SourceFile, list_accounts, log_skipped};
+use crate::importer::suggester::Suggester;
+use crate::output::OutputMode;
 
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub enum TransactionType {
@@ -44,6 +57,18 @@ impl TransactionType {
             Self::Other => "Other",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Debit" => Some(Self::Debit),
+            "Credit" => Some(Self::Credit),
+            "Pos" => Some(Self::Pos),
+            "Atm" => Some(Self::Atm),
+            "Fee" => Some(Self::Fee),
+            "Other" => Some(Self::Other),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -57,69 +82,161 @@ pub struct Transaction<'a> {
     pub memo: Option<Cow<'a, str>>,
 }
 
-async fn list_accounts(
-    accounts: &[AccountConfig],
-    file_queue: Sender<(String, PathBuf)>,
-    list_progress: &ProgressBar,
-) -> Result<()> {
-    let mut stack = Vec::new();
-    for account in accounts {
-        stack.push(account.source_path.clone());
-
-        while let Some(dir) = stack.pop() {
-            let mut read_dir = tokio::fs::read_dir(dir).await?;
-
-            while let Some(entry) = read_dir.next_entry().await? {
-                let entry_type = entry.file_type().await?;
-
-                if entry_type.is_dir() {
-                    stack.push(entry.path());
-                } else if entry_type.is_file() {
-                    list_progress.inc_length(1);
-
-                    file_queue
-                        .send((account.name.clone(), entry.path()))
-                        .await?;
-                } else if entry_type.is_symlink() {
-                    let new_path = tokio::fs::read_link(entry.path()).await?;
-                    let new_meta = tokio::fs::metadata(&new_path).await?;
-
-                    if new_meta.is_file() {
-                        list_progress.inc_length(1);
-
-                        file_queue.send((account.name.clone(), new_path)).await?;
-                    } else if new_meta.is_dir() {
-                        stack.push(entry.path());
-                    }
-                }
-            }
+impl Transaction<'_> {
+    /// Identity of the transaction that stays the same across re-imports.
+    /// Uses the FITID when the file provides one, otherwise a fingerprint of the contents.
+    /// Repeated fingerprints within a file are numbered by `FileKeys`.
+    pub fn key(&self, account: &str) -> String {
+        match &self.transaction_id {
+            Some(id) => format!("{}:{}", account, id),
+            // Amounts always have two decimals, as NUMERIC(16, 2) prints them
+            None => format!(
+                "{}:{}:{:.2}:{}",
+                account, self.date_posted, self.amount, self.name
+            ),
         }
     }
+}
 
-    Ok(())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceKind {
+    Opening,
+    Closing,
 }
 
-pub trait TransactionReader {
-    async fn load(self, importer: TransactionImporter<'_>, progress: &ProgressBar) -> Result<()>;
+impl BalanceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Opening => "opening",
+            Self::Closing => "closing",
+        }
+    }
+}
+
+/// An account balance stated in a statement file
+#[derive(Debug, Clone, Copy)]
+pub struct StatementBalance {
+    pub kind: BalanceKind,
+    pub date: NaiveDate,
+    pub amount: Decimal,
+}
+
+/// Destination for transactions read out of a file
+pub trait TransactionSink {
+    async fn import(&mut self, transaction: Transaction<'_>) -> Result<()>;
+
+    /// Receive a balance from the file, for formats that state them
+    async fn balance(&mut self, _balance: StatementBalance) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A statement file format. Readers are listed in `format.rs`.
+pub trait TransactionReader: Sized {
+    /// Name used in account config and by `money formats`
+    const NAME: &str;
+    const DESCRIPTION: &str;
+    /// Lowercase file extensions that hint at the format
+    const EXTENSIONS: &[&str];
+
+    /// Whether `head`, the start of a file without any byte order mark or leading whitespace,
+    /// looks like this format
+    fn sniff(head: &[u8]) -> bool;
+
+    async fn open(path: &Path) -> Result<Self>;
+
+    async fn load(self, sink: impl TransactionSink, progress: &ProgressBar) -> Result<()>;
+}
+
+/// State shared by every imported file
+pub struct ImportContext<'a> {
+    pub db: &'a Db,
+    pub categorizer: &'a Categorizer,
+    pub suggester: &'a Suggester,
+    pub overrides: &'a HashMap<String, String>,
+    /// Log every skipped file, and why
+    pub verbose: bool,
+    /// Progress bars when fancy, otherwise every imported file is logged unless quiet
+    pub output: OutputMode,
+    /// Transactions skipped because one with the same key was already stored
+    pub duplicates: AtomicUsize,
+}
+
+impl ImportContext<'_> {
+    /// Warn about transactions that weren't stored, since a key shared by different
+    /// transactions loses all but the first
+    pub fn report_duplicates(&self) {
+        let duplicates = self.duplicates.load(Ordering::Relaxed);
+        if duplicates > 0 {
+            eprintln!(
+                "{}Skipped {} transactions already stored under the same key",
+                Emoji("⚠️ ", ""),
+                duplicates
+            );
+        }
+    }
 }
 
 struct ImportConfig<'a> {
-    db: &'a Db,
-    categorizer: &'a Categorizer,
+    context: &'a ImportContext<'a>,
     account_name: String,
     file_path: PathBuf,
+    format: Option<FileFormat>,
+    tracking: FileTracking,
     multi_progress: &'a MultiProgress,
     list_progress: &'a ProgressBar,
 }
 
+/// How `import_file` recognizes a file that was imported before.
+/// Imported files are recorded unless tracking is off.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FileTracking {
+    /// Import every time, for stdin
+    Off,
+    /// Same path and contents, for files found in the source directories
+    Path,
+    /// Same contents under any path, for files named on the command line
+    Contents,
+}
+
 pub struct TransactionImporter<'c> {
     conn: DbHandle,
     categorizer: &'c Categorizer,
+    suggester: &'c Suggester,
+    /// Manual categories keyed by `Transaction::key`, taking priority over the rules
+    overrides: &'c HashMap<String, String>,
+    duplicates: &'c AtomicUsize,
     account_name: String,
+    keys: FileKeys,
+}
+
+/// Keys for the transactions of one file, in file order
+#[derive(Default)]
+struct FileKeys {
+    /// How often each fingerprint key has been seen in the file so far
+    fingerprints: HashMap<String, usize>,
 }
 
-impl<'c> TransactionImporter<'c> {
-    pub async fn import<'t>(&mut self, transaction: Transaction<'t>) -> Result<()> {
+impl FileKeys {
+    /// `Transaction::key`, with a number added to fingerprints seen earlier in the file,
+    /// so identical purchases on the same day are kept apart
+    fn key(&mut self, account: &str, transaction: &Transaction<'_>) -> String {
+        let key = transaction.key(account);
+        if transaction.transaction_id.is_some() {
+            return key;
+        }
+
+        let seen = self.fingerprints.entry(key.clone()).or_default();
+        *seen += 1;
+        match *seen {
+            1 => key,
+            n => format!("{}:{}", key, n),
+        }
+    }
+}
+
+impl TransactionSink for TransactionImporter<'_> {
+    async fn import(&mut self, transaction: Transaction<'_>) -> Result<()> {
         if let Some(tid) = transaction.transaction_id.as_ref()
             && tid.contains(".")
             && transaction.amount.is_zero()
@@ -128,122 +245,205 @@ impl<'c> TransactionImporter<'c> {
             return Ok(());
         }
 
-        let categorization_result = self.categorizer.categorize(
-            &self.account_name,
-            &transaction.name,
-            transaction.transaction_type,
-            transaction.memo.as_ref().map(|m| m.as_ref()),
-        )?;
-        let categorization = match categorization_result {
-            CategorizationStatus::Categorized(c) => c,
-            CategorizationStatus::Uncategorized(t) => {
-                self.conn.add_uncategorized_transaction(t).await?;
+        let categorization_result = self
+            .categorizer
+            .categorize(&self.account_name, &transaction)?;
+        let key = self.keys.key(&self.account_name, &transaction);
+        let category_override = self.overrides.get(&key).map(|c| c.as_str());
+
+        let (user_type, income, category, tags) = match (categorization_result, category_override) {
+            (CategorizationStatus::Categorized(c), Some(category)) => {
+                (c.transaction_type, c.income, category, c.tags)
+            }
+            (CategorizationStatus::Categorized(c), None) if c.ignore => return Ok(()),
+            (CategorizationStatus::Categorized(c), None) => {
+                (c.transaction_type, c.income, c.category, c.tags)
+            }
+            (
+                CategorizationStatus::Uncategorized(UncategorizedTransaction::MissingRule {
+                    transaction_type,
+                    income,
+                    ..
+                }),
+                Some(category),
+            ) => (transaction_type, income, category, &[][..]),
+            (CategorizationStatus::Uncategorized(t), _) => {
+                let suggestions = match &t {
+                    UncategorizedTransaction::MissingRule {
+                        transaction_type,
+                        display,
+                        ..
+                    } => self.suggester.suggest(*transaction_type, display),
+                    UncategorizedTransaction::MissingType { .. } => Vec::new(),
+                };
+                if !self
+                    .conn
+                    .add_uncategorized_transaction(&key, t, &transaction, &suggestions)
+                    .await?
+                {
+                    self.duplicates.fetch_add(1, Ordering::Relaxed);
+                }
                 return Ok(());
             }
         };
 
-        if categorization.ignore {
-            return Ok(());
-        }
-
-        self.conn
-            .add_transaction(&self.account_name, categorization, transaction)
+        let added = self
+            .conn
+            .add_transaction(
+                &self.account_name,
+                &key,
+                &AssignedCategory {
+                    category,
+                    user_type,
+                    income,
+                    tags,
+                },
+                transaction,
+            )
             .await?;
+        if !added {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        }
 
         Ok(())
     }
+
+    async fn balance(&mut self, balance: StatementBalance) -> Result<()> {
+        self.conn
+            .set_statement_balance(&self.account_name, &balance)
+            .await
+    }
 }
 
 async fn import_file(config: ImportConfig<'_>) -> Result<()> {
-    let mut db_handle = config.db.open_handle().await?;
+    let mut db_handle = config.context.db.open_handle().await?;
 
-    let file_name = config
-        .file_path
-        .file_name()
-        .ok_or_else(|| eyre!("Missing file name: {:?}", config.file_path))?
-        .to_str()
-        .ok_or_else(|| eyre!("Filename is not valid utf-8: {:?}", config.file_path))?;
+    // Files are tracked by contents as well as path, so statements that are downloaded
+    // again with changes get imported
+    let loaded = if config.tracking != FileTracking::Off {
+        let file_path = config
+            .file_path
+            .to_str()
+            .ok_or_else(|| eyre!("File path is not valid utf-8: {:?}", config.file_path))?;
+        let contents = tokio::fs::read(&config.file_path)
+            .await
+            .wrap_err_with(|| format!("Failed to read {}", config.file_path.to_string_lossy()))?;
+        let file_hash = Sha256::digest(&contents);
+
+        let checked_path = match config.tracking {
+            FileTracking::Contents => None,
+            _ => Some(file_path),
+        };
+        if db_handle
+            .check_loaded_file(checked_path, &file_hash)
+            .await?
+        {
+            // Named files are expected to be imported, so always say why they weren't
+            if config.context.verbose || config.tracking == FileTracking::Contents {
+                log_skipped(config.list_progress, &config.file_path, "already imported");
+            }
+            config.list_progress.inc(1);
+            return Ok(());
+        }
+        Some((file_path, file_hash))
+    } else {
+        None
+    };
 
-    if db_handle.check_loaded_file(file_name).await? {
-        config.list_progress.inc(1);
+    // Skipped and failed files aren't marked as loaded, so they are checked again on the
+    // next run
+    let Some((format, progress)) = open_file(
+        &config.file_path,
+        config.format,
+        config.multi_progress,
+        config.list_progress,
+    )
+    .await?
+    else {
         return Ok(());
+    };
+
+    let importer = TransactionImporter {
+        conn: db_handle,
+        categorizer: config.context.categorizer,
+        suggester: config.context.suggester,
+        overrides: config.context.overrides,
+        duplicates: &config.context.duplicates,
+        account_name: config.account_name.clone(),
+        keys: FileKeys::default(),
+    };
+
+    format.load(&config.file_path, importer, &progress).await?;
+    if let Some((file_path, file_hash)) = loaded {
+        // The importer's connection was released when loading finished
+        config
+            .context
+            .db
+            .open_handle()
+            .await?
+            .add_loaded_file(file_path, &file_hash)
+            .await?;
+    }
+    if config.context.output == OutputMode::Plain {
+        eprintln!(
+            "Imported {} into {}",
+            config.file_path.to_string_lossy(),
+            config.account_name
+        );
     }
 
-    db_handle.add_loaded_file(file_name).await?;
+    config.list_progress.inc(1);
+    config.multi_progress.remove(&progress);
 
-    let ext = config
-        .file_path
-        .extension()
-        .ok_or_else(|| eyre!("File missing extension: {:?}", config.file_path))?
-        .to_ascii_lowercase();
+    Ok(())
+}
+
+/// Detect the format of `file_path` unless it is known, and add a progress bar for loading it.
+/// Files in an unrecognized format are logged and counted as done.
+async fn open_file(
+    file_path: &Path,
+    format: Option<FileFormat>,
+    multi_progress: &MultiProgress,
+    list_progress: &ProgressBar,
+) -> Result<Option<(FileFormat, ProgressBar)>> {
+    let format = match format {
+        Some(format) => Some(format),
+        None => FileFormat::detect(file_path).await?,
+    };
+    let Some(format) = format else {
+        multi_progress.suspend(|| {
+            eprintln!(
+                "{}Skipped {}: not a recognized statement format",
+                Emoji("⚠️ ", ""),
+                file_path.to_string_lossy()
+            )
+        });
+        list_progress.inc(1);
+        return Ok(None);
+    };
 
     let style =
         ProgressStyle::with_template("[{elapsed:.white}] {spinner:.green} {pos:>6.cyan} {msg}")
             .unwrap();
 
-    let progress = config
-        .multi_progress
-        .insert_before(config.list_progress, ProgressBar::no_length());
+    let progress = multi_progress.insert_before(list_progress, ProgressBar::no_length());
     progress.set_style(style);
     progress.set_message(format!(
         "{}Loading: {}",
         Emoji("📄 ", ""),
-        config
-            .file_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
+        file_path.file_name().and_then(|n| n.to_str()).unwrap_or("")
     ));
 
-    let importer = TransactionImporter {
-        conn: db_handle,
-        categorizer: config.categorizer,
-        account_name: config.account_name,
-    };
-
-    match &*ext.to_string_lossy() {
-        "qfx" => {
-            QfxReader::open(&config.file_path)
-                .await
-                .wrap_err_with(|| {
-                    format!(
-                        "Failed to open file: {}",
-                        config.file_path.to_string_lossy()
-                    )
-                })?
-                .load(importer, &progress)
-                .await?;
-        }
-        "csv" => {
-            CsvReader::open(&config.file_path)
-                .await
-                .wrap_err_with(|| {
-                    format!(
-                        "Failed to open file: {}",
-                        config.file_path.to_string_lossy()
-                    )
-                })?
-                .load(importer, &progress)
-                .await?;
-        }
-        ext => return Err(eyre!("Unrecognized file type: {}", ext)),
-    }
-
-    config.list_progress.inc(1);
-    config.multi_progress.remove(&progress);
-
-    Ok(())
+    Ok(Some((format, progress)))
 }
 
-pub async fn import_files(
-    db: &Db,
-    categorizer: &Categorizer,
-    accounts: &[AccountConfig],
-) -> Result<()> {
-    // Load transactions concurrently
-    let (file_tx, file_rx) = tokio::sync::mpsc::channel(8);
-
-    let multi_progress = MultiProgress::new();
+/// Progress bars for a batch of files, with a bar counting the files themselves
+fn file_progress(output: OutputMode) -> (MultiProgress, ProgressBar) {
+    let multi_progress = if output.progress_bars() {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    };
     let list_style = ProgressStyle::with_template(
         "[{elapsed:.white}] {spinner:.green} {pos:>4.white}/{len:4.white} [{bar:40.cyan}]",
     )
@@ -254,15 +454,50 @@ pub async fn import_files(
     list_progress.set_style(list_style);
     list_progress.enable_steady_tick(Duration::from_millis(250));
 
-    let account_listing = list_accounts(accounts, file_tx, &list_progress);
+    (multi_progress, list_progress)
+}
+
+/// Make stored transaction keys unique before importing, reporting any rows that changed
+pub async fn migrate_keys(db: &Db) -> Result<()> {
+    let migration = db.migrate_keys().await?;
+    for (count, change) in [
+        (
+            migration.removed_transactions,
+            "transactions stored twice under the same FITID were removed",
+        ),
+        (
+            migration.numbered_transactions,
+            "repeated transactions were numbered to keep them apart",
+        ),
+        (
+            migration.removed_uncategorized,
+            "uncategorized transactions stored twice were removed",
+        ),
+    ] {
+        if count > 0 {
+            eprintln!("{}{} {}", Emoji("⚠️ ", ""), count, change);
+        }
+    }
+
+    Ok(())
+}
+
+/// Import every file in the source directories of `accounts`
+pub async fn import_files(context: &ImportContext<'_>, accounts: &[AccountConfig]) -> Result<()> {
+    // Load transactions concurrently
+    let (file_tx, file_rx) = tokio::sync::mpsc::channel(8);
+    let (multi_progress, list_progress) = file_progress(context.output);
+
+    let account_listing = list_accounts(accounts, file_tx, &list_progress, context.verbose);
     let file_loading = ReceiverStream::new(file_rx)
-        .map(|(account_name, file_path)| {
+        .map(|file| {
             // Funky stuff to get all required state to the concurrent function
             Ok(ImportConfig {
-                db,
-                categorizer,
-                account_name,
-                file_path,
+                context,
+                account_name: file.account_name,
+                file_path: file.path,
+                format: file.format,
+                tracking: FileTracking::Path,
                 multi_progress: &multi_progress,
                 list_progress: &list_progress,
             })
@@ -272,3 +507,154 @@ pub async fn import_files(
     futures::future::try_join(account_listing, file_loading).await?;
     Ok(())
 }
+
+/// Import files named on the command line into `account`, one after the other.
+/// A path of `-` reads stdin.
+pub async fn import_paths(
+    context: &ImportContext<'_>,
+    account: &AccountConfig,
+    format: Option<FileFormat>,
+    paths: &[PathBuf],
+) -> Result<()> {
+    if paths.iter().filter(|p| p.as_os_str() == "-").count() > 1 {
+        bail!("stdin (-) can only be imported once");
+    }
+    let format = match format {
+        Some(format) => Some(format),
+        None => FileFormat::pinned(account)?,
+    };
+
+    let (multi_progress, list_progress) = file_progress(context.output);
+    list_progress.set_length(paths.len() as u64);
+
+    for path in paths {
+        // Removed when dropped, after its file is imported
+        let spooled = match path.as_os_str() == "-" {
+            true => Some(spool_stdin().await?),
+            false => None,
+        };
+        let file_path = match &spooled {
+            Some(spooled) => spooled.path().to_path_buf(),
+            None => tokio::fs::canonicalize(path)
+                .await
+                .wrap_err_with(|| format!("Failed to open file: {}", path.to_string_lossy()))?,
+        };
+
+        import_file(ImportConfig {
+            context,
+            account_name: account.name.clone(),
+            file_path,
+            format,
+            tracking: match spooled {
+                Some(_) => FileTracking::Off,
+                None => FileTracking::Contents,
+            },
+            multi_progress: &multi_progress,
+            list_progress: &list_progress,
+        })
+        .await?;
+    }
+
+    list_progress.finish_and_clear();
+    Ok(())
+}
+
+/// Import one file found by watch mode
+pub async fn import_source(context: &ImportContext<'_>, file: SourceFile) -> Result<()> {
+    let (multi_progress, list_progress) = file_progress(context.output);
+    import_file(ImportConfig {
+        context,
+        account_name: file.account_name,
+        file_path: file.path,
+        format: file.format,
+        tracking: FileTracking::Path,
+        multi_progress: &multi_progress,
+        list_progress: &list_progress,
+    })
+    .await
+}
+
+/// Copy stdin to a temporary file, since readers work on paths
+async fn spool_stdin() -> Result<NamedTempFile> {
+    let spooled = tempfile::Builder::new()
+        .prefix("money-stdin-")
+        .tempfile()
+        .wrap_err("Failed to create a temporary file for stdin")?;
+
+    let mut file = tokio::fs::File::from_std(
+        spooled
+            .reopen()
+            .wrap_err("Failed to open the temporary file for stdin")?,
+    );
+    tokio::io::copy(&mut tokio::io::stdin(), &mut file)
+        .await
+        .wrap_err("Failed to read stdin")?;
+    file.flush().await.wrap_err("Failed to read stdin")?;
+
+    Ok(spooled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(amount: Decimal, name: &str, id: Option<&str>) -> Transaction<'static> {
+        Transaction {
+            transaction_type: TransactionType::Debit,
+            date_posted: NaiveDate::from_ymd_opt(2025, 1, 6).unwrap(),
+            amount,
+            transaction_id: id.map(|id| Cow::Owned(id.to_string())),
+            category: None,
+            name: Cow::Owned(name.to_string()),
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn key_matches_stored_amounts() {
+        // The migration builds keys from NUMERIC(16, 2) columns, which print two decimals
+        for (amount, expected) in [
+            (Decimal::new(-51, 1), "chequing:2025-01-06:-5.10:SHOP"),
+            (Decimal::new(12, 0), "chequing:2025-01-06:12.00:SHOP"),
+            (
+                Decimal::new(-123456, 2),
+                "chequing:2025-01-06:-1234.56:SHOP",
+            ),
+        ] {
+            assert_eq!(transaction(amount, "SHOP", None).key("chequing"), expected);
+        }
+        assert_eq!(
+            transaction(Decimal::new(12, 0), "SHOP", Some("F1")).key("chequing"),
+            "chequing:F1"
+        );
+    }
+
+    #[test]
+    fn file_keys_number_repeats() {
+        let shop = transaction(Decimal::new(-5, 0), "SHOP", None);
+        let cafe = transaction(Decimal::new(-5, 0), "CAFE", None);
+        let fitid = transaction(Decimal::new(-5, 0), "SHOP", Some("F1"));
+
+        // Same numbering as the migration's row_number() over rows in id order
+        let mut keys = FileKeys::default();
+        let numbered =
+            [&shop, &shop, &cafe, &fitid, &shop, &fitid].map(|t| keys.key("chequing", t));
+        assert_eq!(
+            numbered,
+            [
+                "chequing:2025-01-06:-5.00:SHOP",
+                "chequing:2025-01-06:-5.00:SHOP:2",
+                "chequing:2025-01-06:-5.00:CAFE",
+                "chequing:F1",
+                "chequing:2025-01-06:-5.00:SHOP:3",
+                "chequing:F1",
+            ]
+        );
+
+        // Each file starts counting again
+        assert_eq!(
+            FileKeys::default().key("chequing", &shop),
+            "chequing:2025-01-06:-5.00:SHOP"
+        );
+    }
+}
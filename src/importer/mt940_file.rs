@@ -0,0 +1,392 @@
+// SWIFT MT940 customer statements
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use chrono::{Datelike, NaiveDate};
+use color_eyre::Result;
+use color_eyre::eyre::{Context, OptionExt, bail, eyre};
+use encoding_rs::WINDOWS_1252;
+use indicatif::ProgressBar;
+use rust_decimal::Decimal;
+
+use crate::importer::{
+    BalanceKind, StatementBalance, Transaction, TransactionReader, TransactionSink, TransactionType,
+};
+
+/// Tags that may appear but carry nothing the importer uses
+const IGNORED_TAGS: &[&str] = &["13D", "21", "34F", "64", "65", "90C", "90D", "NS"];
+
+/// One `:tag:value` field, with continuation lines joined by newlines
+struct Field {
+    line: usize,
+    tag: String,
+    value: String,
+}
+
+/// A `:61:` statement line waiting for its `:86:` narrative
+struct StatementLine {
+    date: NaiveDate,
+    amount: Decimal,
+    credit: bool,
+    /// The bank's reference, which identifies the entry
+    reference: Option<String>,
+    details: Option<String>,
+}
+
+impl StatementLine {
+    fn into_transaction(self, narrative: Option<&str>) -> Transaction<'static> {
+        let (name, memo) = match narrative {
+            Some(narrative) => split_narrative(narrative),
+            None => (None, None),
+        };
+
+        Transaction {
+            transaction_type: if self.credit {
+                TransactionType::Credit
+            } else {
+                TransactionType::Debit
+            },
+            date_posted: self.date,
+            amount: if self.credit {
+                self.amount
+            } else {
+                -self.amount
+            },
+            transaction_id: self.reference.map(Cow::Owned),
+            category: None,
+            name: Cow::Owned(name.or(self.details).unwrap_or_default()),
+            memo: memo.map(Cow::Owned),
+        }
+    }
+}
+
+pub struct Mt940Reader {
+    contents: String,
+}
+
+impl Mt940Reader {
+    fn fields(&self) -> Result<Vec<Field>> {
+        let mut fields: Vec<Field> = Vec::new();
+        for (idx, line) in self.contents.lines().enumerate() {
+            let line = line.trim_end();
+
+            // SWIFT envelope blocks and statement terminators
+            if line.is_empty() || line == "-" || line == "-}" || line.starts_with('{') {
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix(':')
+                && let Some((tag, value)) = rest.split_once(':')
+                && !tag.is_empty()
+                && tag.len() <= 3
+                && tag.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                fields.push(Field {
+                    line: idx + 1,
+                    tag: tag.to_string(),
+                    value: value.to_string(),
+                });
+                continue;
+            }
+
+            let field = fields
+                .last_mut()
+                .ok_or_else(|| eyre!("Line {}: Text before the first field", idx + 1))?;
+            field.value.push('\n');
+            field.value.push_str(line);
+        }
+
+        Ok(fields)
+    }
+}
+
+impl TransactionReader for Mt940Reader {
+    const NAME: &str = "mt940";
+    const DESCRIPTION: &str = "SWIFT MT940 customer statements";
+    const EXTENSIONS: &[&str] = &["sta", "mt940", "940"];
+
+    /// Whether the start of a file looks like an MT940 statement
+    fn sniff(head: &[u8]) -> bool {
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines().map(str::trim_start);
+        lines.clone().any(|l| l.starts_with(":20:")) && lines.any(|l| l.starts_with(":25:"))
+    }
+
+    async fn open(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read(path)
+            .await
+            .wrap_err("Failed to open file")?;
+
+        // Banks mostly use Latin-1 or Windows-1252 unless the file is valid UTF-8
+        let contents = contents.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&contents);
+        let contents = match str::from_utf8(contents) {
+            Ok(contents) => contents.to_string(),
+            Err(_) => WINDOWS_1252.decode(contents).0.into_owned(),
+        };
+
+        Ok(Self { contents })
+    }
+
+    async fn load(self, mut sink: impl TransactionSink, progress: &ProgressBar) -> Result<()> {
+        let mut account: Option<String> = None;
+        let mut pending: Option<StatementLine> = None;
+        let mut transactions = Vec::new();
+
+        for field in self.fields()? {
+            // A statement line without a narrative is complete once any other field starts
+            if field.tag != "86"
+                && let Some(line) = pending.take()
+            {
+                transactions.push(line.into_transaction(None));
+            }
+
+            let result: Result<()> = async {
+                match field.tag.as_str() {
+                    "20" => account = None,
+                    "25" => account = Some(field.value.trim().to_string()),
+                    "28C" | "28" => {}
+                    "60F" | "60M" | "62F" | "62M" => {
+                        if account.is_none() {
+                            bail!("Balance before the account (:25:)");
+                        }
+                        let (date, amount) = parse_balance(&field.value)?;
+                        // Intermediate balances only mark page breaks
+                        let kind = match field.tag.as_str() {
+                            "60F" => BalanceKind::Opening,
+                            "62F" => BalanceKind::Closing,
+                            _ => return Ok(()),
+                        };
+                        sink.balance(StatementBalance { kind, date, amount })
+                            .await?;
+                    }
+                    "61" => {
+                        if account.is_none() {
+                            bail!("Statement line before the account (:25:)");
+                        }
+                        pending = Some(parse_statement_line(&field.value)?);
+                    }
+                    "86" => {
+                        let line = pending
+                            .take()
+                            .ok_or_eyre("Narrative (:86:) without a statement line (:61:)")?;
+                        transactions.push(line.into_transaction(Some(&field.value)));
+                    }
+                    tag if IGNORED_TAGS.contains(&tag) => {}
+                    tag => bail!("Unknown field :{}:", tag),
+                }
+                Ok(())
+            }
+            .await;
+            result.wrap_err_with(|| format!("Line {}", field.line))?;
+        }
+        transactions.extend(pending.map(|line| line.into_transaction(None)));
+
+        for (i, transaction) in transactions.into_iter().enumerate() {
+            sink.import(transaction).await?;
+
+            if i.is_multiple_of(100) {
+                progress.inc(100);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a `:60F:`/`:62F:` balance like `C250301EUR1000,00`
+fn parse_balance(value: &str) -> Result<(NaiveDate, Decimal)> {
+    let value = value.trim();
+    let credit = match value.get(..1) {
+        Some("C") => true,
+        Some("D") => false,
+        _ => bail!("Invalid balance {:?}", value),
+    };
+    let date = value
+        .get(1..7)
+        .ok_or_else(|| eyre!("Invalid balance {:?}", value))
+        .and_then(parse_date)?;
+    let amount = value
+        .get(10..)
+        .ok_or_else(|| eyre!("Invalid balance {:?}", value))
+        .and_then(parse_amount)?;
+
+    Ok((date, if credit { amount } else { -amount }))
+}
+
+/// Parse a `:61:` statement line such as `2503020302DR45,10NTRFNONREF//B5C02`
+fn parse_statement_line(value: &str) -> Result<StatementLine> {
+    let (first, details) = match value.split_once('\n') {
+        Some((first, details)) => (first, Some(details.replace('\n', " "))),
+        None => (value, None),
+    };
+    let invalid = || eyre!("Invalid statement line {:?}", first);
+
+    let value_date = first.get(..6).ok_or_else(invalid).and_then(parse_date)?;
+    let mut rest = &first[6..];
+
+    // The optional booking date only has a month and day
+    let mut date = value_date;
+    if let Some(entry) = rest
+        .get(..4)
+        .filter(|s| s.chars().all(|c| c.is_ascii_digit()))
+    {
+        let month = entry[..2].parse().map_err(|_| invalid())?;
+        let day = entry[2..].parse().map_err(|_| invalid())?;
+        let year = match (value_date.month(), month) {
+            (12, 1) => value_date.year() + 1,
+            (1, 12) => value_date.year() - 1,
+            _ => value_date.year(),
+        };
+        date = NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)?;
+        rest = &rest[4..];
+    }
+
+    // A reversed credit takes money out, a reversed debit puts it back
+    let (credit, mark_len) = if rest.starts_with("RC") {
+        (false, 2)
+    } else if rest.starts_with("RD") {
+        (true, 2)
+    } else if rest.starts_with('C') {
+        (true, 1)
+    } else if rest.starts_with('D') {
+        (false, 1)
+    } else {
+        return Err(invalid());
+    };
+    rest = &rest[mark_len..];
+
+    // Optional funds code, the last letter of the currency
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        rest = &rest[1..];
+    }
+
+    let amount_len = rest
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .ok_or_else(invalid)?;
+    let amount = parse_amount(&rest[..amount_len])?;
+
+    // Skip the transaction type code, such as NTRF. The customer reference before `//`
+    // repeats across entries, like NONREF or an invoice number, so only the bank's
+    // reference identifies one. Without it the transaction contents do.
+    let references = rest.get(amount_len + 4..).ok_or_else(invalid)?;
+    let reference = references
+        .split_once("//")
+        .map(|(_, bank)| bank.trim())
+        .filter(|r| !r.is_empty())
+        .map(str::to_string);
+
+    Ok(StatementLine {
+        date,
+        amount,
+        credit,
+        reference,
+        details: details
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty()),
+    })
+}
+
+/// Split an `:86:` narrative into a name and memo.
+/// Structured narratives like `166?00SEPA?20Invoice 42?32Shop` keep the name in `?32`/`?33`
+/// and the remittance text in `?20` to `?29`, cut into fixed-width pieces mid-word.
+/// Otherwise the first line is the name.
+fn split_narrative(narrative: &str) -> (Option<String>, Option<String>) {
+    let non_empty = |s: String| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+
+    if let Some(subfields) = narrative.get(3..).filter(|s| s.starts_with('?')) {
+        let joined = subfields.replace('\n', "");
+        let mut name = String::new();
+        let mut memo = String::new();
+        for part in joined.split('?').skip(1) {
+            let (Some(code), Some(text)) = (part.get(..2), part.get(2..)) else {
+                continue;
+            };
+            match code {
+                "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" => {
+                    memo.push_str(text)
+                }
+                "32" | "33" => name.push_str(text),
+                _ => {}
+            }
+        }
+        return (non_empty(name), non_empty(memo));
+    }
+
+    let name = narrative.lines().next().unwrap_or_default().to_string();
+    (non_empty(name), non_empty(narrative.replace('\n', " ")))
+}
+
+/// Parse a `YYMMDD` date
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("20{}", value), "%Y%m%d")
+        .wrap_err_with(|| format!("Invalid date {:?}", value))
+}
+
+/// Parse an amount with a decimal comma, such as `1000,00` or `45,`
+fn parse_amount(value: &str) -> Result<Decimal> {
+    let normalized = value.trim().replace(',', ".");
+    let normalized = normalized.strip_suffix('.').unwrap_or(&normalized);
+    Decimal::from_str_exact(normalized).wrap_err_with(|| format!("Invalid amount {:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn statement_line_with_booking_date() {
+        let line = parse_statement_line("2503020303DR45,10NTRFNONREF//B5C02").unwrap();
+        assert_eq!(line.date, date(2025, 3, 3));
+        assert_eq!(line.amount, Decimal::new(4510, 2));
+        assert!(!line.credit);
+        assert_eq!(line.reference.as_deref(), Some("B5C02"));
+        assert_eq!(line.details, None);
+    }
+
+    #[test]
+    fn statement_line_booked_in_the_next_year() {
+        let line = parse_statement_line("2412310102C1000,NMSCINV 42").unwrap();
+        assert_eq!(line.date, date(2025, 1, 2));
+        assert_eq!(line.amount, Decimal::new(1000, 0));
+        assert!(line.credit);
+        // Only the customer reference, which doesn't identify the entry
+        assert_eq!(line.reference, None);
+    }
+
+    #[test]
+    fn statement_line_reversal_and_details() {
+        let line =
+            parse_statement_line("250302RCR12,NTRFNONREF//\nSUPPLEMENTARY\nDETAILS").unwrap();
+        assert_eq!(line.date, date(2025, 3, 2));
+        assert!(!line.credit);
+        assert_eq!(line.amount, Decimal::new(12, 0));
+        assert_eq!(line.reference, None);
+        assert_eq!(line.details.as_deref(), Some("SUPPLEMENTARY DETAILS"));
+    }
+
+    #[test]
+    fn statement_line_rejects_invalid() {
+        assert!(parse_statement_line("250302X12,NTRF").is_err());
+        assert!(parse_statement_line("2503").is_err());
+    }
+
+    #[test]
+    fn structured_narrative() {
+        let (name, memo) =
+            split_narrative("166?00SEPA-UEBERWEISUNG?20Invoice 4\n2 paid?32Shop?33 GmbH");
+        assert_eq!(name.as_deref(), Some("Shop GmbH"));
+        assert_eq!(memo.as_deref(), Some("Invoice 42 paid"));
+    }
+
+    #[test]
+    fn plain_narrative() {
+        let (name, memo) = split_narrative("SHOP GMBH\nINVOICE 42");
+        assert_eq!(name.as_deref(), Some("SHOP GMBH"));
+        assert_eq!(memo.as_deref(), Some("SHOP GMBH INVOICE 42"));
+    }
+}
@@ -15,9 +15,10 @@ use rust_decimal::Decimal;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 
+use crate::importer::format;
 use crate::importer::qfx_file::header::StringEncoding;
 use crate::importer::qfx_file::lexer::{Lexer, QfxToken};
-use crate::importer::{Transaction, TransactionImporter, TransactionReader, TransactionType};
+use crate::importer::{Transaction, TransactionReader, TransactionSink, TransactionType};
 
 pub struct QfxReader {
     contents: Vec<u8>,
@@ -25,8 +26,19 @@ pub struct QfxReader {
     encoding: StringEncoding,
 }
 
-impl QfxReader {
-    pub async fn open(path: &Path) -> Result<Self> {
+impl TransactionReader for QfxReader {
+    const NAME: &str = "qfx";
+    const DESCRIPTION: &str = "OFX and QFX downloads with an SGML or XML header";
+    const EXTENSIONS: &[&str] = &["qfx", "ofx", "qbo"];
+
+    /// Whether the start of a file has an OFX SGML header, or an XML header with an OFX
+    /// processing instruction
+    fn sniff(head: &[u8]) -> bool {
+        head.starts_with(b"OFXHEADER:")
+            || (head.starts_with(b"<") && format::contains(head, b"OFXHEADER="))
+    }
+
+    async fn open(path: &Path) -> Result<Self> {
         let mut reader = BufReader::new(File::open(path).await.wrap_err("Failed to open file")?);
 
         // Determine header type
@@ -89,14 +101,8 @@ impl QfxReader {
             encoding,
         })
     }
-}
 
-impl TransactionReader for QfxReader {
-    async fn load(
-        self,
-        mut importer: TransactionImporter<'_>,
-        progress: &ProgressBar,
-    ) -> Result<()> {
+    async fn load(self, mut sink: impl TransactionSink, progress: &ProgressBar) -> Result<()> {
         let lexer = Lexer::new(self.contents, self.encoding, self.is_xml);
         let parser = DocumentParser::new(lexer);
 
@@ -112,19 +118,18 @@ impl TransactionReader for QfxReader {
             };
             let date = transaction.date_posted.date_naive();
 
-            importer
-                .import(Transaction {
-                    transaction_type: file_transaction_type,
-                    date_posted: date,
-                    amount: transaction.amount,
-                    transaction_id: Some(transaction.transaction_id),
-                    category: None,
-                    name: transaction.name,
-                    memo: transaction.memo,
-                })
-                .await?;
-
-            if i % 100 == 0 {
+            sink.import(Transaction {
+                transaction_type: file_transaction_type,
+                date_posted: date,
+                amount: transaction.amount,
+                transaction_id: Some(transaction.transaction_id),
+                category: None,
+                name: transaction.name,
+                memo: transaction.memo,
+            })
+            .await?;
+
+            if i.is_multiple_of(100) {
                 progress.inc(100);
             }
 
@@ -0,0 +1,325 @@
+// Quicken Interchange Format, as exported by Quicken and many credit unions
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use color_eyre::Result;
+use color_eyre::eyre::{Context, OptionExt, bail, eyre};
+use indicatif::ProgressBar;
+use rust_decimal::Decimal;
+
+use crate::importer::{Transaction, TransactionReader, TransactionSink, TransactionType};
+
+/// Date fields split into their parts, before the day and month order is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QifDate {
+    Ymd(i32, u32, u32),
+    /// Day and month in file order, followed by the year
+    Ambiguous(u32, u32, i32),
+}
+
+#[derive(Debug)]
+struct QifSplit {
+    category: String,
+    amount: Option<Decimal>,
+}
+
+#[derive(Debug, Default)]
+struct QifRecord {
+    /// Line the record starts on, for error messages
+    line: usize,
+    date: Option<QifDate>,
+    amount: Option<Decimal>,
+    payee: Option<String>,
+    memo: Option<String>,
+    number: Option<String>,
+    category: Option<String>,
+    splits: Vec<QifSplit>,
+}
+
+impl QifRecord {
+    fn into_transaction(self, day_first: bool) -> Result<Transaction<'static>> {
+        let date = match self.date.ok_or_eyre("Missing date (D)")? {
+            QifDate::Ymd(y, m, d) => NaiveDate::from_ymd_opt(y, m, d),
+            QifDate::Ambiguous(a, b, y) if day_first => NaiveDate::from_ymd_opt(y, b, a),
+            QifDate::Ambiguous(a, b, y) => NaiveDate::from_ymd_opt(y, a, b),
+        }
+        .ok_or_eyre("Invalid date")?;
+        let amount = self.amount.ok_or_eyre("Missing amount (T or U)")?;
+
+        let split_amounts = self.splits.iter().filter_map(|s| s.amount);
+        if !self.splits.is_empty() && split_amounts.clone().sum::<Decimal>() != amount {
+            bail!(
+                "Split amounts add up to {}, but the transaction amount is {}",
+                split_amounts.sum::<Decimal>(),
+                amount
+            );
+        }
+
+        // Split transactions are imported whole, with the split categories as the source category
+        let category = if self.splits.is_empty() {
+            self.category
+        } else {
+            let categories = self
+                .splits
+                .iter()
+                .map(|s| s.category.as_str())
+                .filter(|c| !c.is_empty())
+                .collect::<Vec<_>>();
+            Some(categories.join(", ")).filter(|c| !c.is_empty())
+        };
+
+        Ok(Transaction {
+            transaction_type: if amount.is_sign_negative() {
+                TransactionType::Debit
+            } else {
+                TransactionType::Credit
+            },
+            date_posted: date,
+            amount,
+            // QIF has no FITID. N values such as cheque numbers, "ATM" or a bank's "0" on
+            // every row aren't unique, so they are kept in the memo instead.
+            transaction_id: None,
+            category: category.map(Cow::Owned),
+            name: Cow::Owned(self.payee.unwrap_or_default()),
+            memo: match (self.memo, self.number.filter(|n| !n.is_empty())) {
+                (Some(memo), Some(number)) => Some(format!("{} #{}", memo, number)),
+                (None, Some(number)) => Some(format!("#{}", number)),
+                (memo, None) => memo,
+            }
+            .map(Cow::Owned),
+        })
+    }
+}
+
+pub struct QifReader {
+    contents: String,
+}
+
+/// Collects records line by line
+#[derive(Default)]
+struct QifParser {
+    in_section: bool,
+    record: Option<QifRecord>,
+    records: Vec<QifRecord>,
+}
+
+impl QifParser {
+    fn parse(contents: &str) -> Result<Vec<QifRecord>> {
+        let mut parser = Self::default();
+        for (idx, line) in contents.lines().enumerate() {
+            parser
+                .parse_line(idx + 1, line.trim_end())
+                .wrap_err_with(|| format!("Line {}", idx + 1))?;
+        }
+
+        // The last record is allowed to leave out the closing ^
+        parser.records.extend(parser.record.take());
+        Ok(parser.records)
+    }
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<()> {
+        let Some(code) = line.chars().next() else {
+            return Ok(());
+        };
+        let value = line[code.len_utf8()..].trim();
+
+        if code == '!' {
+            if self.record.is_some() {
+                bail!("Header inside a record, missing ^");
+            }
+            match value.to_ascii_lowercase().as_str() {
+                "type:bank" | "type:ccard" => self.in_section = true,
+                _ => bail!("Unsupported section {:?}", line),
+            }
+            return Ok(());
+        }
+        if !self.in_section {
+            bail!("Record before a !Type:Bank or !Type:CCard header");
+        }
+
+        if code == '^' {
+            self.records.extend(self.record.take());
+            return Ok(());
+        }
+
+        let record = self.record.get_or_insert_with(|| QifRecord {
+            line: line_number,
+            ..Default::default()
+        });
+        match code {
+            'D' => record.date = Some(parse_date(value)?),
+            // U repeats T with room for larger amounts
+            'T' => record.amount = Some(parse_amount(value)?),
+            'U' => {
+                if record.amount.is_none() {
+                    record.amount = Some(parse_amount(value)?);
+                }
+            }
+            'P' => record.payee = Some(value.to_string()),
+            'M' => record.memo = Some(value.to_string()),
+            'N' => record.number = Some(value.to_string()),
+            'L' => record.category = Some(value.to_string()),
+            'S' => record.splits.push(QifSplit {
+                category: value.to_string(),
+                amount: None,
+            }),
+            // Split memos have nowhere to go
+            'E' => {
+                record
+                    .splits
+                    .last()
+                    .ok_or_eyre("Split memo (E) before a split (S)")?;
+            }
+            '$' => {
+                let split = record
+                    .splits
+                    .last_mut()
+                    .ok_or_eyre("Split amount ($) before a split (S)")?;
+                if split.amount.is_some() {
+                    bail!("Split has more than one amount");
+                }
+                split.amount = Some(parse_amount(value)?);
+            }
+            // Cleared status and payee address don't map to any transaction field
+            'C' | 'A' => {}
+            code => bail!("Unknown record code {:?}", code),
+        }
+
+        Ok(())
+    }
+}
+
+impl TransactionReader for QifReader {
+    const NAME: &str = "qif";
+    const DESCRIPTION: &str = "Quicken Interchange Format bank and credit card sections";
+    const EXTENSIONS: &[&str] = &["qif"];
+
+    /// Whether a file starts with a bank or credit card section header
+    fn sniff(head: &[u8]) -> bool {
+        let first_line = head.split(|b| *b == b'\n').next().unwrap_or_default();
+        let header = first_line.trim_ascii().to_ascii_lowercase();
+        header == b"!type:bank" || header == b"!type:ccard"
+    }
+
+    async fn open(path: &Path) -> Result<Self> {
+        let contents = tokio::fs::read(path)
+            .await
+            .wrap_err("Failed to open file")?;
+
+        let contents = contents.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&contents);
+        Ok(Self {
+            contents: String::from_utf8_lossy(contents).into_owned(),
+        })
+    }
+
+    async fn load(self, mut sink: impl TransactionSink, progress: &ProgressBar) -> Result<()> {
+        let records = QifParser::parse(&self.contents)?;
+
+        // Dates like 03/04/2025 are ambiguous, so look for a day above 12 anywhere in the file
+        let mut day_first = false;
+        let mut month_first = false;
+        for date in records.iter().filter_map(|r| r.date) {
+            if let QifDate::Ambiguous(a, b, _) = date {
+                day_first |= a > 12;
+                month_first |= b > 12;
+            }
+        }
+        if day_first && month_first {
+            bail!("Dates mix day/month and month/day order");
+        }
+
+        for (i, record) in records.into_iter().enumerate() {
+            let line = record.line;
+            let transaction = record
+                .into_transaction(day_first)
+                .wrap_err_with(|| format!("Invalid record starting on line {}", line))?;
+
+            sink.import(transaction).await?;
+
+            if i.is_multiple_of(100) {
+                progress.inc(100);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the date formats Quicken and banks use: `2025-01-31`, `1/31/2025`, `1/31/25`,
+/// `1/31'25`, `1/31' 5` and `31.01.2025`. A `'` before the year marks a year in the 2000s,
+/// written with two digits or space padded to one.
+fn parse_date(value: &str) -> Result<QifDate> {
+    let value = value.replace(' ', "");
+    let invalid = || eyre!("Invalid date {:?}", value);
+
+    let mut parts = value.split(['/', '-', '.', '\'']);
+    let (Some(first), Some(second), Some(third), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+    let number = |s: &str| s.parse::<u32>().map_err(|_| invalid());
+    let year = |s: &str| s.parse::<i32>().map_err(|_| invalid());
+
+    if first.len() == 4 {
+        return Ok(QifDate::Ymd(year(first)?, number(second)?, number(third)?));
+    }
+
+    let full_year = match third.len() {
+        4 => year(third)?,
+        1 if value.contains('\'') => 2000 + year(third)?,
+        2 if value.contains('\'') || year(third)? < 70 => 2000 + year(third)?,
+        2 => 1900 + year(third)?,
+        _ => return Err(invalid()),
+    };
+
+    Ok(QifDate::Ambiguous(
+        number(first)?,
+        number(second)?,
+        full_year,
+    ))
+}
+
+/// Parse an amount like `-1,234.56`
+fn parse_amount(value: &str) -> Result<Decimal> {
+    let cleaned = value.replace([',', ' '], "");
+    Decimal::from_str_exact(&cleaned).wrap_err_with(|| format!("Invalid amount {:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_date_forms() {
+        for (value, expected) in [
+            ("2025-01-31", QifDate::Ymd(2025, 1, 31)),
+            ("1/31/2025", QifDate::Ambiguous(1, 31, 2025)),
+            ("1/31/25", QifDate::Ambiguous(1, 31, 2025)),
+            ("1/31/99", QifDate::Ambiguous(1, 31, 1999)),
+            ("1/31'25", QifDate::Ambiguous(1, 31, 2025)),
+            ("1/31'99", QifDate::Ambiguous(1, 31, 2099)),
+            ("1/31' 5", QifDate::Ambiguous(1, 31, 2005)),
+            (" 1/ 2' 5", QifDate::Ambiguous(1, 2, 2005)),
+            ("31.01.2025", QifDate::Ambiguous(31, 1, 2025)),
+        ] {
+            assert_eq!(parse_date(value).unwrap(), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parse_date_rejects_invalid() {
+        for value in [
+            "",
+            "1/31",
+            "1/31/5",
+            "1/31/2025/1",
+            "a/31/2025",
+            "1/31/20255",
+        ] {
+            assert!(parse_date(value).is_err(), "{:?}", value);
+        }
+    }
+}
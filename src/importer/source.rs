@@ -0,0 +1,281 @@
+// Finding the statement files in each account's source directory
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indicatif::ProgressBar;
+use tokio::sync::mpsc::Sender;
+
+use crate::config::AccountConfig;
+use crate::importer::format::FileFormat;
+
+/// A file found in an account's source directory
+pub struct SourceFile {
+    pub account_name: String,
+    /// Format pinned in the account config, instead of detecting it
+    pub format: Option<FileFormat>,
+    pub path: PathBuf,
+}
+
+/// The `include`, `exclude` and `max_depth` settings of an account
+struct SourceFilter<'a> {
+    account: &'a AccountConfig,
+    /// Empty include lists match everything
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl<'a> SourceFilter<'a> {
+    fn new(account: &'a AccountConfig) -> Result<Self> {
+        let include =
+            if account.include.is_empty() {
+                None
+            } else {
+                Some(build_globs(&account.include).wrap_err_with(|| {
+                    format!("Invalid include glob for account {:?}", account.name)
+                })?)
+            };
+        let exclude = build_globs(&account.exclude)
+            .wrap_err_with(|| format!("Invalid exclude glob for account {:?}", account.name))?;
+
+        Ok(Self {
+            account,
+            include,
+            exclude,
+        })
+    }
+
+    /// Why a file is skipped, if it is, checking the directories leading to it as well.
+    /// `relative` is the file's path below `source_path`.
+    fn file_skip_reason(&self, relative: &Path) -> Option<String> {
+        let mut dir = PathBuf::new();
+        for (depth, component) in relative.parent()?.components().enumerate() {
+            dir.push(component);
+            if let Some(reason) = self.skip_reason(&dir, depth, true) {
+                return Some(reason);
+            }
+        }
+
+        let depth = relative.components().count() - 1;
+        self.skip_reason(relative, depth, false)
+    }
+
+    /// Why a directory entry is skipped, if it is. `relative` is the entry's path below
+    /// `source_path`, and `depth` counts the directories in between.
+    fn skip_reason(&self, relative: &Path, depth: usize, is_dir: bool) -> Option<String> {
+        if let Some(&idx) = self.exclude.matches(relative).first() {
+            return Some(format!("excluded by {:?}", self.account.exclude[idx]));
+        }
+        if is_dir {
+            if let Some(max_depth) = self.account.max_depth
+                && depth + 1 > max_depth
+            {
+                return Some(format!("deeper than max_depth {}", max_depth));
+            }
+        } else if let Some(include) = &self.include
+            && !include.is_match(relative)
+        {
+            return Some("not matched by any include glob".to_string());
+        }
+
+        None
+    }
+}
+
+fn build_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Print why a file or directory isn't imported
+pub(super) fn log_skipped(progress: &ProgressBar, path: &Path, reason: impl Display) {
+    progress.suspend(|| eprintln!("Skipped {}: {}", path.to_string_lossy(), reason));
+}
+
+/// Identifies a file or directory however it is reached
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+/// `path` must be canonical
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> FileId {
+    path.to_path_buf()
+}
+
+/// Queue every file under the source directories of `accounts`, following symbolic links.
+/// Each underlying file is queued at most once per account, by its canonical path.
+pub(super) async fn list_accounts(
+    accounts: &[AccountConfig],
+    file_queue: Sender<SourceFile>,
+    list_progress: &ProgressBar,
+    verbose: bool,
+) -> Result<()> {
+    for account in accounts {
+        let format = FileFormat::pinned(account)?;
+        let filter = SourceFilter::new(account)?;
+
+        let root = tokio::fs::canonicalize(&account.source_path)
+            .await
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to read source_path of account {:?}: {}",
+                    account.name,
+                    account.source_path.to_string_lossy()
+                )
+            })?;
+        let root_metadata = tokio::fs::metadata(&root).await?;
+
+        let mut visited_dirs = HashSet::from([file_id(&root, &root_metadata)]);
+        let mut queued_files = HashSet::new();
+        // Canonical directory, its path below `source_path` for the globs, and its depth
+        let mut stack = vec![(root, PathBuf::new(), 0)];
+
+        while let Some((dir, relative_dir, depth)) = stack.pop() {
+            let mut read_dir = tokio::fs::read_dir(&dir)
+                .await
+                .wrap_err_with(|| format!("Failed to read directory {}", dir.to_string_lossy()))?;
+
+            while let Some(entry) = read_dir.next_entry().await? {
+                let path = entry.path();
+                let relative = relative_dir.join(entry.file_name());
+                // Where the entry appears to be, for messages
+                let shown = account.source_path.join(&relative);
+
+                // Canonicalizing resolves relative links against the link's own directory
+                let target = if entry.file_type().await?.is_symlink() {
+                    match tokio::fs::canonicalize(&path).await {
+                        Ok(target) => target,
+                        Err(e) => {
+                            if verbose {
+                                log_skipped(
+                                    list_progress,
+                                    &shown,
+                                    format!("broken symbolic link ({})", e),
+                                );
+                            }
+                            continue;
+                        }
+                    }
+                } else {
+                    path.clone()
+                };
+                let metadata = tokio::fs::metadata(&target).await?;
+                if !metadata.is_dir() && !metadata.is_file() {
+                    continue;
+                }
+
+                if let Some(reason) = filter.skip_reason(&relative, depth, metadata.is_dir()) {
+                    if verbose {
+                        log_skipped(list_progress, &shown, reason);
+                    }
+                    continue;
+                }
+
+                let id = file_id(&target, &metadata);
+                if metadata.is_dir() {
+                    if visited_dirs.insert(id) {
+                        stack.push((target, relative, depth + 1));
+                    } else if verbose {
+                        log_skipped(list_progress, &shown, "directory already searched");
+                    }
+                } else if queued_files.insert(id) {
+                    list_progress.inc_length(1);
+
+                    file_queue
+                        .send(SourceFile {
+                            account_name: account.name.clone(),
+                            format,
+                            path: target,
+                        })
+                        .await?;
+                } else if verbose {
+                    log_skipped(list_progress, &shown, "same file as one already queued");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// An account source directory in watch mode
+struct WatchedAccount<'a> {
+    root: PathBuf,
+    filter: SourceFilter<'a>,
+    format: Option<FileFormat>,
+}
+
+/// The source directories of every account, for matching changed files in watch mode
+pub struct WatchedAccounts<'a> {
+    accounts: Vec<WatchedAccount<'a>>,
+}
+
+impl<'a> WatchedAccounts<'a> {
+    pub fn new(accounts: &'a [AccountConfig]) -> Result<Self> {
+        let accounts = accounts
+            .iter()
+            .map(|account| {
+                let root = std::fs::canonicalize(&account.source_path).wrap_err_with(|| {
+                    format!(
+                        "Failed to read source_path of account {:?}: {}",
+                        account.name,
+                        account.source_path.to_string_lossy()
+                    )
+                })?;
+                Ok(WatchedAccount {
+                    root,
+                    filter: SourceFilter::new(account)?,
+                    format: FileFormat::pinned(account)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { accounts })
+    }
+
+    /// Canonical source directories
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.accounts.iter().map(|a| a.root.as_path())
+    }
+
+    /// The files to import for a changed path. A file can belong to more than one account
+    /// when source directories overlap.
+    pub fn find(&self, path: &Path, verbose: bool) -> Vec<SourceFile> {
+        let mut files = Vec::new();
+        for watched in &self.accounts {
+            let Ok(relative) = path.strip_prefix(&watched.root) else {
+                continue;
+            };
+
+            match watched.filter.file_skip_reason(relative) {
+                Some(reason) => {
+                    if verbose {
+                        log_skipped(&ProgressBar::hidden(), path, reason);
+                    }
+                }
+                None => files.push(SourceFile {
+                    account_name: watched.filter.account.name.clone(),
+                    format: watched.format,
+                    path: path.to_path_buf(),
+                }),
+            }
+        }
+        files
+    }
+}
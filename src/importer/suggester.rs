@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::{TransactionRuleConfig, UserTransactionType};
+
+/// Number of suggestions kept for each uncategorized transaction
+const MAX_SUGGESTIONS: usize = 3;
+/// Suggestions scoring below this are dropped
+const MIN_CONFIDENCE: f32 = 0.35;
+/// Penalty applied to patterns that belong to a different transaction type
+const OTHER_TYPE_WEIGHT: f32 = 0.8;
+
+const SIMILARITY_WEIGHT: f32 = 0.5;
+const TOKEN_WEIGHT: f32 = 0.3;
+const FREQUENCY_WEIGHT: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Suggestion {
+    pub category: &'static str,
+    pub confidence: f32,
+}
+
+struct PatternEntry {
+    transaction_type: UserTransactionType,
+    pattern: String,
+    tokens: HashSet<String>,
+    category: &'static str,
+}
+
+/// Ranks candidate categories for display names that have no matching rule
+pub struct Suggester {
+    patterns: Vec<PatternEntry>,
+    /// Mapping of name tokens to how often each category was assigned to a transaction containing it
+    /// `{token: {category: count}}`
+    token_categories: HashMap<String, HashMap<&'static str, usize>>,
+}
+
+impl Suggester {
+    /// Build a suggester from the configured rules and the `(category, name)` pairs of transactions
+    /// that were already categorized
+    pub fn build(
+        rules: &'static [TransactionRuleConfig],
+        categorized: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        let mut patterns = Vec::new();
+        for rule in rules.iter().filter(|r| !r.ignore) {
+            for pattern in &rule.patterns {
+                patterns.push(PatternEntry {
+                    transaction_type: rule.transaction_type,
+                    pattern: normalize(pattern),
+                    tokens: tokenize(pattern),
+                    category: rule.category.as_str(),
+                });
+            }
+        }
+
+        let categories: HashSet<&'static str> = patterns.iter().map(|p| p.category).collect();
+
+        let mut token_categories: HashMap<String, HashMap<&'static str, usize>> = HashMap::new();
+        for (category, name) in categorized {
+            // Only learn categories that rules can still produce
+            let Some(&category) = categories.get(category.as_str()) else {
+                continue;
+            };
+
+            for token in tokenize(&name) {
+                *token_categories
+                    .entry(token)
+                    .or_default()
+                    .entry(category)
+                    .or_default() += 1;
+            }
+        }
+
+        Self {
+            patterns,
+            token_categories,
+        }
+    }
+
+    /// Rank categories for `display`, best first
+    pub fn suggest(&self, transaction_type: UserTransactionType, display: &str) -> Vec<Suggestion> {
+        let normalized = normalize(display);
+        let tokens = tokenize(display);
+
+        let mut similarity: HashMap<&'static str, f32> = HashMap::new();
+        let mut overlap: HashMap<&'static str, f32> = HashMap::new();
+        for entry in &self.patterns {
+            let weight = if entry.transaction_type == transaction_type {
+                1.0
+            } else {
+                OTHER_TYPE_WEIGHT
+            };
+
+            let s = strsim::jaro_winkler(&normalized, &entry.pattern) as f32 * weight;
+            let best = similarity.entry(entry.category).or_default();
+            *best = best.max(s);
+
+            let t = jaccard(&tokens, &entry.tokens) * weight;
+            let best = overlap.entry(entry.category).or_default();
+            *best = best.max(t);
+        }
+
+        let frequency = self.token_frequency(&tokens);
+
+        let mut suggestions = similarity
+            .into_iter()
+            .map(|(category, s)| {
+                let t = overlap.get(category).copied().unwrap_or_default();
+                let f = frequency.get(category).copied().unwrap_or_default();
+                Suggestion {
+                    category,
+                    confidence: SIMILARITY_WEIGHT * s + TOKEN_WEIGHT * t + FREQUENCY_WEIGHT * f,
+                }
+            })
+            .filter(|s| s.confidence >= MIN_CONFIDENCE)
+            .collect::<Vec<_>>();
+
+        suggestions.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| a.category.cmp(b.category))
+        });
+        suggestions.truncate(MAX_SUGGESTIONS);
+        suggestions
+    }
+
+    /// Average share of each category across the known tokens in `tokens`
+    fn token_frequency(&self, tokens: &HashSet<String>) -> HashMap<&'static str, f32> {
+        let mut frequency: HashMap<&'static str, f32> = HashMap::new();
+        let known = tokens
+            .iter()
+            .filter_map(|t| self.token_categories.get(t))
+            .collect::<Vec<_>>();
+
+        for counts in &known {
+            let total = counts.values().sum::<usize>() as f32;
+            for (&category, &count) in counts.iter() {
+                *frequency.entry(category).or_default() += count as f32 / total;
+            }
+        }
+
+        for share in frequency.values_mut() {
+            *share /= known.len() as f32;
+        }
+
+        frequency
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value.trim().to_uppercase()
+}
+
+/// Split a name into upper-case words, skipping reference numbers and other tokens containing digits
+fn tokenize(value: &str) -> HashSet<String> {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.len() > 1 && !t.chars().any(|c| c.is_ascii_digit()))
+        .map(str::to_uppercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+
+    a.intersection(b).count() as f32 / union as f32
+}
@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+use color_eyre::Result;
+use color_eyre::eyre::{OptionExt, bail};
+use rust_decimal::Decimal;
+
+use crate::categories::CategoryTree;
+use crate::config::{AccountConfig, AccountKind, CategoryKind, UserTransactionType};
+use crate::db::ReportTransaction;
+
+/// Transfers posted this many days apart in the two accounts are still paired up
+const TRANSFER_DAYS: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalStyle {
+    /// Ledger and hledger
+    Ledger,
+    Beancount,
+}
+
+/// What to include besides the postings
+#[derive(Debug, Clone, Copy)]
+pub struct JournalOptions<'a> {
+    /// Commodity written after every amount. Required by beancount.
+    pub currency: Option<&'a str>,
+    /// Add the transaction type and memo as metadata
+    pub raw: bool,
+    pub tags: bool,
+}
+
+struct Posting {
+    account: String,
+    amount: Decimal,
+    metadata: Vec<(&'static str, String)>,
+}
+
+struct Entry<'t> {
+    date: NaiveDate,
+    /// Lowest key of the transactions in the entry. Keys survive re-imports, unlike row ids.
+    key: &'t str,
+    name: &'t str,
+    tags: BTreeSet<&'t str>,
+    postings: Vec<Posting>,
+}
+
+/// All `report_transactions` rows of one stored transaction
+struct Group<'t> {
+    parts: Vec<&'t ReportTransaction>,
+}
+
+impl<'t> Group<'t> {
+    fn first(&self) -> &'t ReportTransaction {
+        self.parts[0]
+    }
+
+    fn date(&self) -> Result<NaiveDate> {
+        self.first()
+            .posted_date
+            .ok_or_eyre("Stored transaction is missing a posted date")
+    }
+
+    fn amount(&self) -> Result<Decimal> {
+        self.parts
+            .iter()
+            .map(|p| {
+                p.amount
+                    .ok_or_eyre("Stored transaction is missing an amount")
+            })
+            .sum()
+    }
+
+    fn is_transfer(&self) -> bool {
+        self.parts.len() == 1
+            && self.first().user_transaction_type
+                == UserTransactionType::InterAccountTransfer.name()
+    }
+}
+
+/// Maps stored accounts and categories to journal account names
+struct AccountNames<'c> {
+    accounts: HashMap<&'c str, String>,
+    categories: &'c CategoryTree,
+}
+
+impl<'c> AccountNames<'c> {
+    fn new(accounts: &'c [AccountConfig], categories: &'c CategoryTree) -> Self {
+        let accounts = accounts
+            .iter()
+            .map(|a| {
+                let name = match &a.ledger_name {
+                    Some(name) => name
+                        .split(':')
+                        .map(account_segment)
+                        .collect::<Vec<_>>()
+                        .join(":"),
+                    None => {
+                        let root = match a.kind {
+                            AccountKind::Asset => "Assets",
+                            AccountKind::Liability => "Liabilities",
+                        };
+                        format!("{}:{}", root, account_segment(&a.name))
+                    }
+                };
+                (a.name.as_str(), name)
+            })
+            .collect();
+
+        Self {
+            accounts,
+            categories,
+        }
+    }
+
+    fn account(&self, account: &str) -> String {
+        self.accounts
+            .get(account)
+            .cloned()
+            .unwrap_or_else(|| format!("Assets:{}", account_segment(account)))
+    }
+
+    fn category(&self, transaction: &ReportTransaction) -> String {
+        let kind = match self.categories.get(&transaction.category) {
+            Some(category) => category.kind,
+            None if transaction.income == Some(true) => CategoryKind::Income,
+            None => CategoryKind::Expense,
+        };
+        let root = match kind {
+            CategoryKind::Income => "Income",
+            CategoryKind::Expense => "Expenses",
+        };
+
+        let mut name = root.to_string();
+        for segment in transaction.category.split('.') {
+            name.push(':');
+            name.push_str(&account_segment(segment));
+        }
+        name
+    }
+}
+
+/// Turn a name into a journal account segment: a capital letter or digit followed by
+/// letters, digits and dashes, which suits both ledger and beancount
+fn account_segment(name: &str) -> String {
+    let mut segment = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            if segment.is_empty() {
+                segment.extend(c.to_uppercase());
+            } else {
+                segment.push(c);
+            }
+        } else if !segment.is_empty() && !segment.ends_with('-') {
+            segment.push('-');
+        }
+    }
+
+    let segment = segment.trim_end_matches('-');
+    if segment.is_empty() {
+        "Unknown".to_string()
+    } else {
+        segment.to_string()
+    }
+}
+
+/// Metadata added to the account posting of every transaction
+fn posting_metadata(
+    transaction: &ReportTransaction,
+    options: &JournalOptions,
+) -> Vec<(&'static str, String)> {
+    let mut metadata = Vec::new();
+    if let Some(fitid) = &transaction.transaction_id {
+        metadata.push(("fitid", fitid.clone()));
+    }
+    if options.raw {
+        metadata.push(("type", transaction.transaction_type.clone()));
+        if let Some(memo) = transaction.memo.as_ref().filter(|m| !m.is_empty()) {
+            metadata.push(("memo", memo.clone()));
+        }
+    }
+    metadata
+}
+
+/// Build the journal entries for `transactions`, which must have the parts of each
+/// transaction next to each other
+fn build_entries<'t>(
+    transactions: &'t [ReportTransaction],
+    names: &AccountNames,
+    options: &JournalOptions,
+) -> Result<Vec<Entry<'t>>> {
+    let mut groups: Vec<Group<'t>> = Vec::new();
+    for transaction in transactions {
+        match groups.last_mut() {
+            Some(group) if group.first().id == transaction.id => group.parts.push(transaction),
+            _ => groups.push(Group {
+                parts: vec![transaction],
+            }),
+        }
+    }
+    groups.sort_by(|a, b| {
+        (a.first().posted_date, &a.first().transaction_key)
+            .cmp(&(b.first().posted_date, &b.first().transaction_key))
+    });
+
+    // Pair each outgoing transfer with the closest matching incoming one in another account
+    let mut paired: HashMap<usize, usize> = HashMap::new();
+    let mut taken = BTreeSet::new();
+    for (i, outgoing) in groups.iter().enumerate() {
+        if !outgoing.is_transfer() || !outgoing.amount()?.is_sign_negative() {
+            continue;
+        }
+        let date = outgoing.date()?;
+        let amount = outgoing.amount()?;
+
+        let mut best: Option<(i64, usize)> = None;
+        for (j, incoming) in groups.iter().enumerate() {
+            if !incoming.is_transfer()
+                || taken.contains(&j)
+                || incoming.first().account == outgoing.first().account
+                || incoming.amount()? != -amount
+            {
+                continue;
+            }
+            let days = (incoming.date()? - date).num_days().abs();
+            if days <= TRANSFER_DAYS && best.is_none_or(|(d, _)| days < d) {
+                best = Some((days, j));
+            }
+        }
+
+        if let Some((_, j)) = best {
+            taken.insert(j);
+            paired.insert(i, j);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (i, group) in groups.iter().enumerate() {
+        if taken.contains(&i) {
+            continue;
+        }
+
+        let first = group.first();
+        let mut entry = Entry {
+            date: group.date()?,
+            key: &first.transaction_key,
+            name: &first.name,
+            tags: BTreeSet::new(),
+            postings: vec![Posting {
+                account: names.account(&first.account),
+                amount: group.amount()?,
+                metadata: posting_metadata(first, options),
+            }],
+        };
+
+        if let Some(&j) = paired.get(&i) {
+            let incoming = groups[j].first();
+            entry.key = entry.key.min(&incoming.transaction_key);
+            entry.postings.push(Posting {
+                account: names.account(&incoming.account),
+                amount: groups[j].amount()?,
+                metadata: posting_metadata(incoming, options),
+            });
+            if options.tags {
+                entry.tags.extend(incoming.tags.iter().map(String::as_str));
+            }
+        } else {
+            for part in &group.parts {
+                entry.postings.push(Posting {
+                    account: names.category(part),
+                    amount: -part
+                        .amount
+                        .ok_or_eyre("Stored transaction is missing an amount")?,
+                    metadata: Vec::new(),
+                });
+            }
+        }
+
+        if options.tags {
+            entry.tags.extend(first.tags.iter().map(String::as_str));
+        }
+        entries.push(entry);
+    }
+
+    entries.sort_by_key(|e| (e.date, e.key));
+    Ok(entries)
+}
+
+/// Render `transactions` as a plain-text accounting journal.
+/// The output only depends on the transactions and config, so it can be diffed between runs.
+pub fn render(
+    style: JournalStyle,
+    transactions: &[ReportTransaction],
+    accounts: &[AccountConfig],
+    categories: &CategoryTree,
+    options: &JournalOptions,
+) -> Result<String> {
+    if style == JournalStyle::Beancount && options.currency.is_none() {
+        bail!("Beancount exports need a currency");
+    }
+
+    let names = AccountNames::new(accounts, categories);
+    let entries = build_entries(transactions, &names, options)?;
+
+    // Declare every account at the first date it is used
+    let mut opened: BTreeMap<&str, NaiveDate> = BTreeMap::new();
+    for entry in &entries {
+        for posting in &entry.postings {
+            opened.entry(&posting.account).or_insert(entry.date);
+        }
+    }
+
+    let amount = |value: Decimal| match options.currency {
+        Some(currency) => format!("{:.2} {}", value, currency),
+        None => format!("{:.2}", value),
+    };
+
+    let mut out = String::new();
+    match style {
+        JournalStyle::Ledger => {
+            for account in opened.keys() {
+                writeln!(out, "account {}", account)?;
+            }
+
+            for entry in &entries {
+                writeln!(out, "\n{} * {}", entry.date, entry.name.replace(';', ","))?;
+                for tag in &entry.tags {
+                    writeln!(out, "    ; {}:", tag_name(tag))?;
+                }
+                for posting in &entry.postings {
+                    write!(
+                        out,
+                        "    {:<40}  {:>16}",
+                        posting.account,
+                        amount(posting.amount)
+                    )?;
+                    for (key, value) in &posting.metadata {
+                        write!(out, "  ; {}: {}", key, value.replace(['\n', ','], " "))?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+        }
+        JournalStyle::Beancount => {
+            for (account, date) in &opened {
+                writeln!(out, "{} open {}", date, account)?;
+            }
+
+            for entry in &entries {
+                write!(out, "\n{} * {}", entry.date, beancount_string(entry.name))?;
+                for tag in &entry.tags {
+                    write!(out, " #{}", tag_name(tag))?;
+                }
+                writeln!(out)?;
+                for posting in &entry.postings {
+                    writeln!(
+                        out,
+                        "  {:<40}  {:>16}",
+                        posting.account,
+                        amount(posting.amount)
+                    )?;
+                    for (key, value) in &posting.metadata {
+                        writeln!(out, "    {}: {}", key, beancount_string(value))?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Lowercase a tag and replace anything but letters, digits and dashes
+fn tag_name(tag: &str) -> String {
+    account_segment(tag).to_lowercase()
+}
+
+fn beancount_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -1,16 +1,40 @@
+mod budget;
+mod categories;
 #[deny(clippy::all, clippy::pedantic)]
 mod config;
 mod db;
+mod export;
 mod importer;
+mod journal;
+mod output;
+mod report;
+mod search;
+mod split;
+mod watch;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
 
-use clap::Parser;
+use budget::BudgetPlan;
+use categories::CategoryTree;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
 use color_eyre::Result;
-use color_eyre::eyre::{Context, eyre};
-use config::AppConfig;
-use console::{Emoji, style};
-use importer::categorizer::Categorizer;
+use color_eyre::eyre::{Context, bail, eyre};
+use config::{AccountConfig, AppConfig};
+use console::style;
+use db::{AssignedCategory, TransactionFilter, TransactionSearch};
+use export::{ExportFormat, ExportOptions};
+use importer::ImportContext;
+use importer::audit::{self, RuleAudit};
+use importer::categorizer::{CategorizationStatus, Categorizer, UncategorizedTransaction};
+use importer::format::FileFormat;
+use importer::suggester::Suggester;
+use output::{OutputFormat, OutputMode};
+use report::Report;
+use rust_decimal::Decimal;
+use search::{SearchResults, SearchSort};
+use split::SplitPart;
 
 async fn load_config(config_path: PathBuf) -> Result<AppConfig> {
     tokio::task::spawn_blocking(move || AppConfig::load(&config_path))
@@ -22,62 +46,993 @@ async fn load_config(config_path: PathBuf) -> Result<AppConfig> {
 #[command(version, about, long_about = None)]
 /// A simple expense tracking program
 struct Args {
-    /// Clear the database and re-import all transactions
-    #[arg(long)]
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    /// Same as `import --clean`, kept for scripts written before subcommands
+    #[arg(long, hide = true)]
     clean: bool,
+
+    /// `import` with its defaults when not given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Args, Debug)]
+struct GlobalArgs {
+    /// Config file to read, instead of config.toml in the data directory
+    #[arg(long, global = true, env = "MONEY_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Directory holding config.toml, instead of money_app in the OS user data directory
+    #[arg(long, global = true, env = "MONEY_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Read profiles/NAME.toml in the data directory instead of config.toml. Each profile has
+    /// its own database and accounts.
+    #[arg(long, global = true, env = "MONEY_PROFILE", conflicts_with = "config")]
+    profile: Option<String>,
+
+    /// How progress and status messages are shown
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output_mode: OutputMode,
+
+    /// Log every file that is skipped during import, and why
+    #[arg(long, short, global = true)]
+    verbose: bool,
+}
+
+impl GlobalArgs {
+    fn config_path(&self) -> Result<PathBuf> {
+        if let Some(config) = &self.config {
+            return Ok(config.clone());
+        }
+
+        let data_dir = match &self.data_dir {
+            Some(data_dir) => data_dir.clone(),
+            None => dirs::data_dir()
+                .ok_or_else(|| eyre!("OS user data directory missing, use --data-dir"))?
+                .join("money_app"),
+        };
+
+        let Some(profile) = &self.profile else {
+            return Ok(data_dir.join("config.toml"));
+        };
+        if profile.is_empty() || profile.contains(['/', '\\']) || profile.starts_with('.') {
+            bail!("Invalid profile name {:?}", profile);
+        }
+        let config_path = data_dir.join("profiles").join(format!("{}.toml", profile));
+        if !config_path.exists() {
+            bail!(
+                "No profile named {:?}, expected a config at {}",
+                profile,
+                config_path.to_string_lossy()
+            );
+        }
+        Ok(config_path)
+    }
+}
+
+#[derive(clap::Args, Debug, Default)]
+struct ImportArgs {
+    /// Clear the imported transactions first and re-import every file
+    #[arg(long, conflicts_with_all = ["paths", "account"])]
+    clean: bool,
+    /// Files to import into one account instead of scanning the source directories, or - to
+    /// read stdin
+    #[arg(requires = "account")]
+    paths: Vec<PathBuf>,
+    /// Account the files belong to
+    #[arg(long, requires = "paths")]
+    account: Option<String>,
+    /// Read the files as this format instead of detecting it, see `money formats`
+    #[arg(long, value_parser = parse_format, requires = "paths")]
+    format: Option<FileFormat>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the transaction rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+    /// Manually categorize individual transactions
+    Override {
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+    /// Divide transactions across multiple categories
+    Split {
+        #[command(subcommand)]
+        command: SplitCommand,
+    },
+    /// Compare spending against the configured budgets
+    Budget {
+        /// Number of months to show, ending with the current month
+        #[arg(long, default_value_t = 3)]
+        months: u32,
+    },
+    /// Print monthly totals per category
+    Report {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Number of category segments to group by, 1 groups by base category
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Write stored transactions to a CSV, JSON Lines, ledger or beancount file
+    Export {
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_enum, default_value_t)]
+        format: ExportFormat,
+        /// Include the fields read from the source file: type, FITID, source category and memo
+        #[arg(long)]
+        raw: bool,
+        /// Include the tags of each transaction
+        #[arg(long)]
+        tags: bool,
+        /// Commodity written after journal amounts, required for beancount
+        #[arg(long)]
+        currency: Option<String>,
+        /// File to write, instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Find stored transactions
+    Search {
+        #[command(flatten)]
+        search: SearchArgs,
+        #[arg(long, value_enum, default_value_t)]
+        sort: SearchSort,
+        /// Sort from the newest or largest down
+        #[arg(long)]
+        desc: bool,
+        /// Only print the first N matches, after sorting
+        #[arg(long)]
+        limit: Option<usize>,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Label transactions with free-form tags
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// List the supported statement file formats
+    Formats,
+    /// Keep running and import new statements as they appear in the source directories
+    Watch,
+    /// Import new files from the account source directories, or the given files. The default.
+    Import(ImportArgs),
+    /// Check the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Maintain the database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Load the config and build the rules, categories and budgets without importing
+    Check,
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// Delete imported transactions and balances so the next import reads every file again.
+    /// Overrides, splits and tags are kept.
+    Reset,
+}
+
+#[derive(Subcommand, Debug)]
+enum RulesCommand {
+    /// Report rules that never match, shadowed prefixes and likely typos
+    Audit {
+        /// Audit the transactions stored in the database instead of re-reading the source files
+        #[arg(long)]
+        stored: bool,
+    },
+    /// List display names missing a rule along with suggested categories
+    Suggest,
+}
+
+#[derive(Subcommand, Debug)]
+enum OverrideCommand {
+    /// Give a transaction a category regardless of the rules
+    Set {
+        #[command(flatten)]
+        transaction: TransactionSelector,
+        category: String,
+    },
+    /// Return a transaction to the category given by the rules
+    Clear {
+        #[command(flatten)]
+        transaction: TransactionSelector,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SplitCommand {
+    /// Split a transaction into parts that add up to its amount
+    Set {
+        #[command(flatten)]
+        transaction: TransactionSelector,
+        /// A part written as CATEGORY=AMOUNT. One part may leave out the amount to take the rest.
+        #[arg(long = "part", required = true)]
+        parts: Vec<SplitPart>,
+    },
+    /// Return a split transaction to a single category
+    Clear {
+        #[command(flatten)]
+        transaction: TransactionSelector,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct FilterArgs {
+    /// Only include transactions posted on or after this date
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Only include transactions posted before this date
+    #[arg(long)]
+    before: Option<NaiveDate>,
+    /// Only include transactions from this account. Can be repeated.
+    #[arg(long = "account")]
+    accounts: Vec<String>,
+}
+
+impl From<FilterArgs> for TransactionFilter {
+    fn from(args: FilterArgs) -> Self {
+        Self {
+            from: args.from,
+            before: args.before,
+            accounts: args.accounts,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
+    /// Text to find in the name or memo, ignoring case
+    text: Option<String>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// Only include amounts of at least this much, ignoring the sign
+    #[arg(long)]
+    min_amount: Option<Decimal>,
+    /// Only include amounts of at most this much, ignoring the sign
+    #[arg(long)]
+    max_amount: Option<Decimal>,
+    /// Only include this category and its sub-categories
+    #[arg(long)]
+    category: Option<String>,
+    /// Only include this transaction type, either the configured type or the type from the file
+    #[arg(long = "type")]
+    transaction_type: Option<String>,
+    /// Also search transactions that could not be categorized
+    #[arg(long)]
+    uncategorized: bool,
+}
+
+impl From<SearchArgs> for TransactionSearch {
+    fn from(args: SearchArgs) -> Self {
+        Self {
+            filter: args.filter.into(),
+            min_amount: args.min_amount,
+            max_amount: args.max_amount,
+            category: args.category,
+            transaction_type: args.transaction_type,
+            text: args.text,
+            uncategorized: args.uncategorized,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum TagCommand {
+    /// Add tags to the selected transactions
+    Add {
+        #[command(flatten)]
+        transactions: TagSelector,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from the selected transactions, including tags applied by rules
+    Remove {
+        #[command(flatten)]
+        transactions: TagSelector,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = true)]
+struct TagSelector {
+    /// Row id of a stored transaction
+    #[arg(long, conflicts_with_all = ["key", "from", "before", "account"])]
+    id: Option<i32>,
+    /// Stable transaction key, built from the account and FITID or the transaction contents
+    #[arg(long, conflicts_with_all = ["from", "before", "account"])]
+    key: Option<String>,
+    /// Select transactions posted on or after this date
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Select transactions posted before this date
+    #[arg(long)]
+    before: Option<NaiveDate>,
+    /// Only select transactions from this account
+    #[arg(long)]
+    account: Option<String>,
+}
+
+impl TagSelector {
+    async fn resolve(self, db: &db::Db) -> Result<Vec<String>> {
+        if self.id.is_some() || self.key.is_some() {
+            let transaction = TransactionSelector {
+                id: self.id,
+                key: self.key,
+            };
+            return Ok(vec![transaction.resolve(db).await?]);
+        }
+
+        db.open_handle()
+            .await?
+            .list_transaction_keys(self.from, self.before, self.account.as_deref())
+            .await
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+struct TransactionSelector {
+    /// Row id of a stored transaction
+    #[arg(long)]
+    id: Option<i32>,
+    /// Stable transaction key, built from the account and FITID or the transaction contents
+    #[arg(long)]
+    key: Option<String>,
+}
+
+impl TransactionSelector {
+    async fn resolve(self, db: &db::Db) -> Result<String> {
+        match (self.id, self.key) {
+            (_, Some(key)) => Ok(key),
+            (Some(id), None) => db
+                .open_handle()
+                .await?
+                .get_transaction_key(id)
+                .await?
+                .ok_or_else(|| eyre!("No transaction with id {}", id)),
+            (None, None) => Err(eyre!("No transaction selected")),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
+    let args = Args::parse();
+    let global = args.global;
+    let output = global.output_mode;
+    output.install();
+
+    output.step(
+        "",
+        style(concat!("Money v", env!("CARGO_PKG_VERSION"))).white(),
+    );
+
+    let command = match args.command {
+        None => Command::Import(ImportArgs {
+            clean: args.clean,
+            ..Default::default()
+        }),
+        Some(_) if args.clean => bail!("--clean is an import option, use `money import --clean`"),
+        Some(command) => command,
+    };
+
+    // Doesn't need a config
+    if let Command::Formats = command {
+        formats();
+        return Ok(());
+    }
+
+    let config_path = global.config_path()?;
+    output.step("", format!("Config: {}\n", config_path.to_string_lossy()));
+
+    // Loads and reloads the config itself
+    if let Command::Watch = command {
+        return watch::watch(&config_path, output, global.verbose).await;
+    }
+
+    // Reports every problem instead of stopping at the first
+    if let Command::Config {
+        command: ConfigCommand::Check,
+    } = command
+    {
+        return config_check(config_path).await;
+    }
+
+    let config = load_config(config_path.clone())
+        .await
+        .map(|c| Box::leak(Box::new(c)))?;
+    let categories = CategoryTree::build(&config.category)
+        .map(|c| &*Box::leak(Box::new(c)))
+        .wrap_err("Failed to load categories")?;
+    let categorizer = Categorizer::build(&config.transaction_type, &config.rule, categories)
+        .map(|c| &*Box::leak(Box::new(c)))
+        .wrap_err("Failed to load transaction rules")?;
+    let budgets =
+        BudgetPlan::build(&config.budget, categories).wrap_err("Failed to load budgets")?;
+
+    match command {
+        Command::Budget { months } => budget(config, &budgets, months).await,
+        Command::Report {
+            filter,
+            depth,
+            format,
+        } => report(config, &filter.into(), depth, format).await,
+        Command::Export {
+            filter,
+            format,
+            raw,
+            tags,
+            currency,
+            output: path,
+        } => {
+            let options = ExportOptions {
+                format,
+                raw,
+                tags,
+                currency: currency.as_deref(),
+            };
+            export(
+                config,
+                categories,
+                &filter.into(),
+                &options,
+                path.as_deref(),
+            )
+            .await
+        }
+        Command::Search {
+            search,
+            sort,
+            desc,
+            limit,
+            format,
+        } => self::search(config, &search.into(), sort, desc, limit, format).await,
+        Command::Import(import_args) => {
+            let target = match &import_args.account {
+                Some(account) => ImportTarget::Paths {
+                    account: config
+                        .account
+                        .iter()
+                        .find(|a| &a.name == account)
+                        .ok_or_else(|| eyre!("No account named {:?} in the config", account))?,
+                    format: import_args.format,
+                    paths: &import_args.paths,
+                },
+                None => ImportTarget::Accounts,
+            };
+            let options = ImportOptions {
+                clean: import_args.clean,
+                verbose: global.verbose,
+                output,
+            };
+            import(config, categories, categorizer, &budgets, options, target).await
+        }
+        Command::Db {
+            command: DbCommand::Reset,
+        } => db_reset(config, output).await,
+        Command::Rules {
+            command: RulesCommand::Audit { stored },
+        } => rules_audit(config, categorizer, stored, output).await,
+        Command::Rules {
+            command: RulesCommand::Suggest,
+        } => rules_suggest(config).await,
+        Command::Override {
+            command:
+                OverrideCommand::Set {
+                    transaction,
+                    category,
+                },
+        } => override_set(config, categories, categorizer, transaction, &category).await,
+        Command::Override {
+            command: OverrideCommand::Clear { transaction },
+        } => override_clear(config, categorizer, transaction).await,
+        Command::Split {
+            command: SplitCommand::Set { transaction, parts },
+        } => split_set(config, categories, transaction, parts).await,
+        Command::Split {
+            command: SplitCommand::Clear { transaction },
+        } => split_clear(config, transaction).await,
+        Command::Tag {
+            command: TagCommand::Add { transactions, tags },
+        } => tag_update(config, transactions, &tags, true).await,
+        Command::Tag {
+            command: TagCommand::Remove { transactions, tags },
+        } => tag_update(config, transactions, &tags, false).await,
+        Command::Formats | Command::Watch | Command::Config { .. } => {
+            unreachable!("Handled before loading the config")
+        }
+    }
+}
+
+fn parse_format(name: &str) -> Result<FileFormat, String> {
+    FileFormat::from_name(name).ok_or_else(|| {
+        let names = FileFormat::ALL.iter().map(|f| f.name()).collect::<Vec<_>>();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+fn formats() {
     println!(
         "{}",
-        style(concat!("Money v", env!("CARGO_PKG_VERSION"))).white()
+        style(format!(
+            "{:<8} {:<20} {}",
+            "Name", "Extensions", "Description"
+        ))
+        .bold()
+        .white()
     );
+    for format in FileFormat::ALL {
+        let extensions = format
+            .extensions()
+            .iter()
+            .map(|e| format!(".{}", e))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "{:<8} {:<20} {}",
+            format.name(),
+            extensions,
+            format.description()
+        );
+    }
+    println!(
+        "\nFiles are detected by their contents. Set `format` on an account to skip detection."
+    );
+}
 
-    let args = Args::parse();
+/// Which files `import` reads
+enum ImportTarget<'a> {
+    /// Every file in the account source directories
+    Accounts,
+    /// Files named on the command line
+    Paths {
+        account: &'a AccountConfig,
+        format: Option<FileFormat>,
+        paths: &'a [PathBuf],
+    },
+}
 
-    let data_dir = dirs::data_dir()
-        .ok_or_else(|| eyre!("OS user data directory missing"))?
-        .join("money_app");
+/// Flags for `import`
+struct ImportOptions {
+    clean: bool,
+    verbose: bool,
+    output: OutputMode,
+}
+
+async fn import(
+    config: &'static AppConfig,
+    categories: &CategoryTree,
+    categorizer: &'static Categorizer,
+    budgets: &BudgetPlan,
+    options: ImportOptions,
+    target: ImportTarget<'_>,
+) -> Result<()> {
+    options.output.step("🏦 ", "Importing transactions...");
+    let db_pool = db::build(&config.database, options.clean)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    importer::migrate_keys(&db_pool).await?;
+
+    let categorized = db_pool
+        .open_handle()
+        .await?
+        .list_categorized_names()
+        .await?;
+    let suggester = Suggester::build(&config.rule, categorized);
+    let mut conn = db_pool.open_handle().await?;
+    conn.sync_categories(categories).await?;
+    let overrides = conn.list_overrides().await?;
+    drop(conn);
 
-    println!("Data directory: {}\n", data_dir.to_string_lossy());
+    let context = ImportContext {
+        db: &db_pool,
+        categorizer,
+        suggester: &suggester,
+        overrides: &overrides,
+        verbose: options.verbose,
+        output: options.output,
+        duplicates: AtomicUsize::new(0),
+    };
+    match target {
+        ImportTarget::Accounts => importer::import_files(&context, &config.account).await?,
+        ImportTarget::Paths {
+            account,
+            format,
+            paths,
+        } => importer::import_paths(&context, account, format, paths).await?,
+    }
+    context.report_duplicates();
+
+    if !budgets.is_empty() {
+        budget::refresh(&db_pool, budgets).await?;
+    }
+
+    options.output.step("✅ ", "Import complete");
+
+    Ok(())
+}
+
+async fn config_check(config_path: PathBuf) -> Result<()> {
+    let path = config_path.clone();
+    let problems = tokio::task::spawn_blocking(move || config::check::check_file(&path))
+        .await?
+        .wrap_err("Failed to load config")?;
+
+    for problem in &problems {
+        println!(
+            "{}:{}:{}: {}",
+            config_path.to_string_lossy(),
+            problem.line,
+            problem.column,
+            problem.message
+        );
+    }
+    if !problems.is_empty() {
+        bail!(
+            "Found {} problems in {}",
+            problems.len(),
+            config_path.to_string_lossy()
+        );
+    }
+
+    // Building everything catches whatever the checks miss, such as rules that can never match
+    let config = load_config(config_path.clone())
+        .await
+        .map(|c| &*Box::leak(Box::new(c)))?;
+    let categories = CategoryTree::build(&config.category)
+        .map(|c| &*Box::leak(Box::new(c)))
+        .wrap_err("Failed to load categories")?;
+    Categorizer::build(&config.transaction_type, &config.rule, categories)
+        .wrap_err("Failed to load transaction rules")?;
+    BudgetPlan::build(&config.budget, categories).wrap_err("Failed to load budgets")?;
 
-    let config_path = data_dir.join("config.toml");
     println!(
-        "[{}] {}Loading config...",
-        style("1/4").bold().white(),
-        Emoji("📄 ", "")
+        "{} is valid: {} accounts, {} transaction types, {} rules, {} categories, {} budgets",
+        config_path.to_string_lossy(),
+        config.account.len(),
+        config.transaction_type.len(),
+        config.rule.len(),
+        config.category.len(),
+        config.budget.len()
     );
-    let config = load_config(config_path)
+
+    Ok(())
+}
+
+async fn db_reset(config: &AppConfig, output: OutputMode) -> Result<()> {
+    output.step("🗑️ ", "Clearing imported transactions...");
+    db::build(&config.database, true)
         .await
-        .map(|c| Box::leak(Box::new(c)))?;
+        .wrap_err("Failed to setup DB")?;
+
+    println!("Database reset, run `money import` to import every file again");
+
+    Ok(())
+}
+
+async fn rules_audit(
+    config: &'static AppConfig,
+    categorizer: &'static Categorizer,
+    stored: bool,
+    output: OutputMode,
+) -> Result<()> {
+    output.step("🔍 ", "Auditing transactions...");
+    let mut audit = RuleAudit::new(config, categorizer);
+    if stored {
+        let db_pool = db::build(&config.database, false)
+            .await
+            .wrap_err("Failed to setup DB")?;
+        audit::audit_stored(&mut audit, &db_pool).await?;
+    } else {
+        audit::audit_files(&mut audit, config, output).await?;
+    }
+
+    output.step("✅ ", "Audit complete");
+    audit.print_report(config);
+
+    Ok(())
+}
+
+async fn rules_suggest(config: &AppConfig) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let missing_rules = db_pool.open_handle().await?.list_missing_rules().await?;
 
     println!(
-        "[{}] {}Building rules...",
-        style("2/4").bold().white(),
-        Emoji("⚙️ ", "")
+        "\n{} display names missing a rule",
+        style(missing_rules.len()).bold().white()
     );
-    let categorizer = Categorizer::build(&config.transaction_type, &config.rule)
-        .wrap_err("Failed to load transaction rules")?;
+    for missing in missing_rules {
+        println!(
+            "\n{} {:?} ({} transactions)",
+            style(&missing.transaction_type).white(),
+            missing.display,
+            missing.count
+        );
+        if missing.suggestions.is_empty() {
+            println!("    {}", style("No suggestions").dim());
+        }
+        for (category, confidence) in missing
+            .suggestions
+            .iter()
+            .zip(&missing.suggestion_confidence)
+        {
+            println!("    {:>3.0}% {}", confidence * 100.0, category);
+        }
+    }
+
+    Ok(())
+}
+
+async fn override_set(
+    config: &AppConfig,
+    categories: &CategoryTree,
+    categorizer: &Categorizer,
+    transaction: TransactionSelector,
+    category: &str,
+) -> Result<()> {
+    categories.check(category)?;
+
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let key = transaction.resolve(&db_pool).await?;
+
+    let mut conn = db_pool.open_handle().await?;
+    conn.set_override(&key, category).await?;
+    let mut updated = conn.set_transaction_category(&key, category).await?;
+
+    // Store uncategorized transactions under the override, as an import would.
+    // Transactions without a known type stay uncategorized.
+    let uncategorized = conn.list_uncategorized_transactions(Some(&key)).await?;
+    if !uncategorized.is_empty() {
+        conn.remove_uncategorized_transactions(&key).await?;
+    }
+    for (account, transaction) in uncategorized {
+        let (user_type, income, tags) = match categorizer.categorize(&account, &transaction)? {
+            CategorizationStatus::Categorized(c) => (c.transaction_type, c.income, c.tags),
+            CategorizationStatus::Uncategorized(UncategorizedTransaction::MissingRule {
+                transaction_type,
+                income,
+                ..
+            }) => (transaction_type, income, &[][..]),
+            CategorizationStatus::Uncategorized(t) => {
+                conn.add_uncategorized_transaction(&key, t, &transaction, &[])
+                    .await?;
+                continue;
+            }
+        };
+        let assigned = AssignedCategory {
+            category,
+            user_type,
+            income,
+            tags,
+        };
+        if conn
+            .add_transaction(&account, &key, &assigned, transaction)
+            .await?
+        {
+            updated += 1;
+        }
+    }
 
     println!(
-        "[{}] {}Loading transaction files...",
-        style("3/4").bold().white(),
-        Emoji("🏦 ", ""),
+        "Set {} for {:?}, updated {} stored transactions",
+        style(category).bold().white(),
+        key,
+        updated
     );
-    let db_pool = db::build(&config.database, args.clean)
+
+    Ok(())
+}
+
+async fn override_clear(
+    config: &'static AppConfig,
+    categorizer: &Categorizer,
+    transaction: TransactionSelector,
+) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
         .await
         .wrap_err("Failed to setup DB")?;
+    let key = transaction.resolve(&db_pool).await?;
 
-    importer::import_files(&db_pool, &categorizer, &config.account).await?;
+    let mut conn = db_pool.open_handle().await?;
+    if !conn.clear_override(&key).await? {
+        println!("No override set for {:?}", key);
+        return Ok(());
+    }
+
+    // Re-run the rules on anything already stored under the override
+    let suggester = Suggester::build(&config.rule, conn.list_categorized_names().await?);
+    for (account, transaction) in conn.list_transactions(Some(&key)).await? {
+        match categorizer.categorize(&account, &transaction)? {
+            CategorizationStatus::Categorized(c) if !c.ignore => {
+                conn.set_transaction_category(&key, c.category).await?;
+            }
+            CategorizationStatus::Categorized(_) => conn.remove_transactions(&key).await?,
+            CategorizationStatus::Uncategorized(t) => {
+                let suggestions = match &t {
+                    UncategorizedTransaction::MissingRule {
+                        transaction_type,
+                        display,
+                        ..
+                    } => suggester.suggest(*transaction_type, display),
+                    UncategorizedTransaction::MissingType { .. } => Vec::new(),
+                };
+                conn.remove_transactions(&key).await?;
+                conn.add_uncategorized_transaction(&key, t, &transaction, &suggestions)
+                    .await?;
+            }
+        }
+    }
+
+    println!("Cleared override for {:?}", key);
+
+    Ok(())
+}
+
+async fn split_set(
+    config: &AppConfig,
+    categories: &CategoryTree,
+    transaction: TransactionSelector,
+    parts: Vec<SplitPart>,
+) -> Result<()> {
+    for part in &parts {
+        categories.check(&part.category)?;
+    }
+
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let key = transaction.resolve(&db_pool).await?;
+
+    let mut conn = db_pool.open_handle().await?;
+    let total = conn
+        .get_transaction_amount(&key)
+        .await?
+        .ok_or_else(|| eyre!("No stored transaction for {:?}", key))?;
+    let parts = split::resolve(parts, total).wrap_err("Invalid split")?;
+    conn.set_splits(&key, &parts).await?;
+
+    println!("Split {:?} ({}):", key, total);
+    for (category, amount) in parts {
+        println!("  {:>12} {}", amount, category);
+    }
+
+    Ok(())
+}
+
+async fn split_clear(config: &AppConfig, transaction: TransactionSelector) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let key = transaction.resolve(&db_pool).await?;
+
+    if db_pool.open_handle().await?.clear_splits(&key).await? {
+        println!("Cleared split for {:?}", key);
+    } else {
+        println!("No split set for {:?}", key);
+    }
+
+    Ok(())
+}
+
+async fn tag_update(
+    config: &AppConfig,
+    transactions: TagSelector,
+    tags: &[String],
+    added: bool,
+) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let keys = transactions.resolve(&db_pool).await?;
+
+    db_pool
+        .open_handle()
+        .await?
+        .set_tags(&keys, tags, added)
+        .await?;
 
     println!(
-        "[{}] {}Import complete",
-        style("4/4").bold().white(),
-        Emoji("✅ ", ""),
+        "{} {} on {} transactions",
+        if added { "Added" } else { "Removed" },
+        style(tags.join(", ")).bold().white(),
+        keys.len()
     );
 
     Ok(())
 }
+
+async fn budget(config: &AppConfig, budgets: &BudgetPlan, months: u32) -> Result<()> {
+    if budgets.is_empty() {
+        println!("No budgets configured");
+        return Ok(());
+    }
+
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+    let results = budget::refresh(&db_pool, budgets).await?;
+    budget::print_report(&results, months)
+}
+
+async fn report(
+    config: &AppConfig,
+    filter: &TransactionFilter,
+    depth: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+
+    Report::build(&db_pool, filter, depth)
+        .await?
+        .write(format)
+        .await
+}
+
+async fn search(
+    config: &AppConfig,
+    search: &TransactionSearch,
+    sort: SearchSort,
+    descending: bool,
+    limit: Option<usize>,
+    format: OutputFormat,
+) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+
+    SearchResults::find(&db_pool, search, sort, descending, limit)
+        .await?
+        .write(format)
+        .await
+}
+
+async fn export(
+    config: &AppConfig,
+    categories: &CategoryTree,
+    filter: &TransactionFilter,
+    options: &ExportOptions<'_>,
+    path: Option<&Path>,
+) -> Result<()> {
+    let db_pool = db::build(&config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+
+    let count =
+        export::export(&db_pool, &config.account, categories, filter, options, path).await?;
+    if let Some(path) = path {
+        eprintln!("Exported {} rows to {}", count, path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use clap::ValueEnum;
+use color_eyre::Result;
+use color_eyre::eyre::Context;
+use console::Emoji;
+use csv_async::AsyncWriter;
+use serde::Serialize;
+use std::fmt::Display;
+use std::path::Path;
+
+use tokio::fs::File;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+
+/// How command results are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable table
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+/// How progress and status messages are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputMode {
+    /// Progress bars, colors and emoji
+    #[default]
+    Fancy,
+    /// One plain line per step or imported file, for logs
+    Plain,
+    /// Only warnings and errors
+    Quiet,
+}
+
+impl OutputMode {
+    /// Turn off colors on stderr unless fancy
+    pub fn install(self) {
+        if self != Self::Fancy {
+            console::set_colors_enabled_stderr(false);
+        }
+    }
+
+    pub fn progress_bars(self) -> bool {
+        self == Self::Fancy
+    }
+
+    /// Print a step of the running command
+    pub fn step(self, emoji: &str, message: impl Display) {
+        match self {
+            Self::Fancy => eprintln!("{}{}", Emoji(emoji, ""), message),
+            Self::Plain => eprintln!("{}", message),
+            Self::Quiet => {}
+        }
+    }
+}
+
+/// Destination for command data, either stdout or a file
+pub type OutputWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Open `path` for writing, or stdout when no path is given
+pub async fn open(path: Option<&Path>) -> Result<OutputWriter> {
+    match path {
+        Some(path) => {
+            let file = File::create(path)
+                .await
+                .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+        None => Ok(Box::new(tokio::io::stdout())),
+    }
+}
+
+pub struct CsvOutput {
+    writer: AsyncWriter<OutputWriter>,
+}
+
+impl CsvOutput {
+    pub fn new() -> Self {
+        Self::from_writer(Box::new(tokio::io::stdout()))
+    }
+
+    pub fn from_writer(writer: OutputWriter) -> Self {
+        Self {
+            writer: AsyncWriter::from_writer(writer),
+        }
+    }
+
+    pub async fn write<I, T>(&mut self, record: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        self.writer
+            .write_record(record)
+            .await
+            .wrap_err("Failed to write CSV record")
+    }
+
+    pub async fn finish(mut self) -> Result<()> {
+        self.writer
+            .flush()
+            .await
+            .wrap_err("Failed to write CSV output")
+    }
+}
+
+/// Write `value` to stdout as a single JSON document
+pub async fn write_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut text = serde_json::to_vec_pretty(value).wrap_err("Failed to serialize JSON")?;
+    text.push(b'\n');
+
+    let mut stdout = tokio::io::stdout();
+    stdout
+        .write_all(&text)
+        .await
+        .wrap_err("Failed to write JSON output")?;
+    stdout.flush().await.wrap_err("Failed to write JSON output")
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use chrono::Months;
+use color_eyre::Result;
+use console::style;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::db::{Db, TransactionFilter};
+use crate::output::{self, CsvOutput, OutputFormat};
+
+#[derive(Debug, Serialize)]
+struct ReportTotals {
+    income: Vec<Decimal>,
+    expenses: Vec<Decimal>,
+    net: Vec<Decimal>,
+}
+
+/// Monthly totals per category. Expenses are shown as positive amounts.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    months: Vec<String>,
+    income: BTreeMap<String, Vec<Decimal>>,
+    expenses: BTreeMap<String, Vec<Decimal>>,
+    totals: ReportTotals,
+}
+
+impl Report {
+    /// Total the stored transactions matching `filter`, grouping categories by their first `depth` segments
+    pub async fn build(db: &Db, filter: &TransactionFilter, depth: usize) -> Result<Self> {
+        let rows = db.open_handle().await?.monthly_totals(filter).await?;
+
+        let months = match (
+            rows.iter().map(|r| r.2).min(),
+            rows.iter().map(|r| r.2).max(),
+        ) {
+            (Some(first), Some(last)) => {
+                let mut months = Vec::new();
+                let mut month = first;
+                while month <= last {
+                    months.push(month);
+                    month = month + Months::new(1);
+                }
+                months
+            }
+            _ => Vec::new(),
+        };
+
+        let mut income: BTreeMap<String, Vec<Decimal>> = BTreeMap::new();
+        let mut expenses: BTreeMap<String, Vec<Decimal>> = BTreeMap::new();
+        for (category, is_income, month, amount) in rows {
+            let category = category
+                .split('.')
+                .take(depth.max(1))
+                .collect::<Vec<_>>()
+                .join(".");
+            let idx = months.binary_search(&month).unwrap();
+
+            let (section, amount) = if is_income {
+                (&mut income, amount)
+            } else {
+                (&mut expenses, -amount)
+            };
+            section
+                .entry(category)
+                .or_insert_with(|| vec![Decimal::ZERO; months.len()])[idx] += amount;
+        }
+
+        let column_totals = |section: &BTreeMap<String, Vec<Decimal>>| {
+            (0..months.len())
+                .map(|i| section.values().map(|v| v[i]).sum::<Decimal>())
+                .collect::<Vec<_>>()
+        };
+        let income_totals = column_totals(&income);
+        let expense_totals = column_totals(&expenses);
+        let net = income_totals
+            .iter()
+            .zip(&expense_totals)
+            .map(|(i, e)| i - e)
+            .collect();
+
+        Ok(Self {
+            months: months
+                .iter()
+                .map(|m| m.format("%Y-%m").to_string())
+                .collect(),
+            income,
+            expenses,
+            totals: ReportTotals {
+                income: income_totals,
+                expenses: expense_totals,
+                net,
+            },
+        })
+    }
+
+    pub async fn write(&self, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Table => {
+                self.print_table();
+                Ok(())
+            }
+            OutputFormat::Csv => self.write_csv().await,
+            OutputFormat::Json => output::write_json(self).await,
+        }
+    }
+
+    fn print_table(&self) {
+        let name_width = self
+            .income
+            .keys()
+            .chain(self.expenses.keys())
+            .map(|c| c.len() + 2)
+            .chain([8])
+            .max()
+            .unwrap_or_default();
+
+        let header = self
+            .months
+            .iter()
+            .map(|m| format!("{:>12}", m))
+            .collect::<String>();
+        println!("{:<name_width$}{}", "", style(header).bold().white());
+
+        let print_row = |name: &str, values: &[Decimal]| {
+            let values = values
+                .iter()
+                .map(|v| format!("{:>12.2}", v))
+                .collect::<String>();
+            println!("{:<name_width$}{}", name, values);
+        };
+
+        for (title, section, totals) in [
+            ("Income", &self.income, &self.totals.income),
+            ("Expenses", &self.expenses, &self.totals.expenses),
+        ] {
+            println!("{}", style(title).bold().white());
+            for (category, values) in section {
+                print_row(&format!("  {}", category), values);
+            }
+            print_row("  Total", totals);
+        }
+
+        println!("{}", style("Net").bold().white());
+        print_row("", &self.totals.net);
+    }
+
+    async fn write_csv(&self) -> Result<()> {
+        let mut csv = CsvOutput::new();
+
+        let mut header = vec!["section".to_string(), "category".to_string()];
+        header.extend(self.months.iter().cloned());
+        csv.write(&header).await?;
+
+        let row = |section: &str, category: &str, values: &[Decimal]| {
+            let mut row = vec![section.to_string(), category.to_string()];
+            row.extend(values.iter().map(|v| format!("{:.2}", v)));
+            row
+        };
+
+        for (category, values) in &self.income {
+            csv.write(row("income", category, values)).await?;
+        }
+        for (category, values) in &self.expenses {
+            csv.write(row("expenses", category, values)).await?;
+        }
+        csv.write(row("total", "income", &self.totals.income))
+            .await?;
+        csv.write(row("total", "expenses", &self.totals.expenses))
+            .await?;
+        csv.write(row("total", "net", &self.totals.net)).await?;
+
+        csv.finish().await
+    }
+}
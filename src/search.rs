@@ -0,0 +1,175 @@
+use clap::ValueEnum;
+use color_eyre::Result;
+use console::style;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::db::{Db, SearchResult, TransactionSearch};
+use crate::output::{self, CsvOutput, OutputFormat};
+
+/// Column search results are ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SearchSort {
+    /// Posted date
+    #[default]
+    Date,
+    /// Amount without the sign
+    Amount,
+    Account,
+    Name,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    transactions: Vec<SearchResult>,
+    count: usize,
+    total: Decimal,
+}
+
+impl SearchResults {
+    /// Run `search`, sorting the matches and keeping the first `limit` of them
+    pub async fn find(
+        db: &Db,
+        search: &TransactionSearch,
+        sort: SearchSort,
+        descending: bool,
+        limit: Option<usize>,
+    ) -> Result<Self> {
+        let mut transactions = db.open_handle().await?.search_transactions(search).await?;
+
+        transactions.sort_by(|a, b| {
+            let ordering = match sort {
+                SearchSort::Date => a.posted_date.cmp(&b.posted_date),
+                SearchSort::Amount => a.amount.map(|v| v.abs()).cmp(&b.amount.map(|v| v.abs())),
+                SearchSort::Account => a.account.cmp(&b.account),
+                SearchSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            };
+            // Keep the order stable between runs when the sort column ties
+            let ordering = ordering
+                .then_with(|| a.posted_date.cmp(&b.posted_date))
+                .then_with(|| a.transaction_key.cmp(&b.transaction_key));
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        if let Some(limit) = limit {
+            transactions.truncate(limit);
+        }
+
+        let total = transactions.iter().filter_map(|t| t.amount).sum();
+        Ok(Self {
+            count: transactions.len(),
+            transactions,
+            total,
+        })
+    }
+
+    pub async fn write(&self, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Table => {
+                self.print_table();
+                Ok(())
+            }
+            OutputFormat::Csv => self.write_csv().await,
+            OutputFormat::Json => output::write_json(self).await,
+        }
+    }
+
+    fn print_table(&self) {
+        let account_width = self
+            .transactions
+            .iter()
+            .map(|t| t.account.len())
+            .chain([7])
+            .max()
+            .unwrap_or_default();
+        // Uncategorized rows are labelled "uncategorized"
+        let category_width = self
+            .transactions
+            .iter()
+            .map(|t| t.category.as_deref().map_or(13, str::len))
+            .chain([8])
+            .max()
+            .unwrap_or_default();
+
+        println!(
+            "{}",
+            style(format!(
+                "{:>8} {:<10} {:<account_width$} {:>12} {:<category_width$} {}",
+                "Id", "Date", "Account", "Amount", "Category", "Name"
+            ))
+            .bold()
+            .white()
+        );
+
+        for transaction in &self.transactions {
+            let date = transaction
+                .posted_date
+                .map_or_else(|| "-".to_string(), |d| d.to_string());
+            let amount = transaction
+                .amount
+                .map_or_else(|| "-".to_string(), |a| format!("{:.2}", a));
+            let category = match &transaction.category {
+                Some(category) => style(format!("{:<category_width$}", category)),
+                None => style(format!("{:<category_width$}", "uncategorized")).yellow(),
+            };
+            let memo = match &transaction.memo {
+                Some(memo) if !memo.is_empty() => format!(" {}", style(memo).dim()),
+                _ => String::new(),
+            };
+
+            println!(
+                "{:>8} {:<10} {:<account_width$} {:>12} {} {}{}",
+                transaction.id, date, transaction.account, amount, category, transaction.name, memo
+            );
+        }
+
+        println!(
+            "\n{} transactions, total {}",
+            style(self.count).bold().white(),
+            style(format!("{:.2}", self.total)).bold().white()
+        );
+    }
+
+    async fn write_csv(&self) -> Result<()> {
+        let mut csv = CsvOutput::new();
+
+        csv.write([
+            "id",
+            "transaction_key",
+            "account",
+            "posted_date",
+            "amount",
+            "category",
+            "transaction_type",
+            "name",
+            "memo",
+        ])
+        .await?;
+
+        for transaction in &self.transactions {
+            csv.write([
+                transaction.id.to_string(),
+                transaction.transaction_key.clone(),
+                transaction.account.clone(),
+                transaction
+                    .posted_date
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+                transaction
+                    .amount
+                    .map(|a| format!("{:.2}", a))
+                    .unwrap_or_default(),
+                transaction.category.clone().unwrap_or_default(),
+                transaction.transaction_type.clone(),
+                transaction.name.clone(),
+                transaction.memo.clone().unwrap_or_default(),
+            ])
+            .await?;
+        }
+
+        csv.finish().await
+    }
+}
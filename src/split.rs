@@ -0,0 +1,138 @@
+use std::str::FromStr;
+
+use color_eyre::Result;
+use color_eyre::eyre::{Context, Report, bail};
+use rust_decimal::Decimal;
+
+/// One part of a split transaction, as given on the command line.
+/// Written as `CATEGORY=AMOUNT`, or just `CATEGORY` to take the remaining amount.
+#[derive(Debug, Clone)]
+pub struct SplitPart {
+    pub category: String,
+    pub amount: Option<Decimal>,
+}
+
+impl FromStr for SplitPart {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (category, amount) = match s.split_once('=') {
+            Some((category, amount)) => {
+                let amount = Decimal::from_str_exact(amount.trim())
+                    .wrap_err_with(|| format!("Invalid split amount: {:?}", amount))?;
+                (category, Some(amount))
+            }
+            None => (s, None),
+        };
+
+        let category = category.trim();
+        if category.is_empty() {
+            bail!("Split part missing a category");
+        }
+
+        Ok(Self {
+            category: category.to_string(),
+            amount,
+        })
+    }
+}
+
+/// Turn the parts into `(category, amount)` pairs that add up to `total`.
+/// Part amounts are given without a sign and take the sign of the transaction.
+pub fn resolve(parts: Vec<SplitPart>, total: Decimal) -> Result<Vec<(String, Decimal)>> {
+    if parts.len() < 2 {
+        bail!("A split needs at least two parts");
+    }
+
+    let remainder_parts = parts.iter().filter(|p| p.amount.is_none()).count();
+    if remainder_parts > 1 {
+        bail!("Only one split part can take the remaining amount");
+    }
+
+    let sign = if total.is_sign_negative() {
+        Decimal::NEGATIVE_ONE
+    } else {
+        Decimal::ONE
+    };
+
+    let mut assigned = Decimal::ZERO;
+    for part in &parts {
+        if let Some(amount) = part.amount {
+            if amount <= Decimal::ZERO {
+                bail!("Split amount for {} must be above zero", part.category);
+            }
+            if amount.scale() > 2 {
+                bail!(
+                    "Split amount for {} has more than two decimals",
+                    part.category
+                );
+            }
+            assigned += amount;
+        }
+    }
+
+    let remaining = total.abs() - assigned;
+    if remaining < Decimal::ZERO {
+        bail!(
+            "Split amounts add up to {}, more than the transaction amount {}",
+            assigned,
+            total.abs()
+        );
+    }
+    if remainder_parts == 0 && !remaining.is_zero() {
+        bail!(
+            "Split amounts add up to {}, but the transaction amount is {}",
+            assigned,
+            total.abs()
+        );
+    }
+    if remainder_parts == 1 && remaining.is_zero() {
+        bail!("Nothing left over for the split part without an amount");
+    }
+
+    Ok(parts
+        .into_iter()
+        .map(|part| (part.category, part.amount.unwrap_or(remaining) * sign))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(values: &[&str]) -> Vec<SplitPart> {
+        values.iter().map(|v| v.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn remainder_takes_the_rest() {
+        let resolved = resolve(parts(&["Food=30.50", "Home"]), Decimal::new(-10000, 2)).unwrap();
+        assert_eq!(
+            resolved,
+            [
+                ("Food".to_string(), Decimal::new(-3050, 2)),
+                ("Home".to_string(), Decimal::new(-6950, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn amounts_must_add_up() {
+        let total = Decimal::new(100, 0);
+        assert!(resolve(parts(&["Food=60", "Home=40"]), total).is_ok());
+        assert!(resolve(parts(&["Food=60", "Home=30"]), total).is_err());
+        assert!(resolve(parts(&["Food=60", "Home=50"]), total).is_err());
+        assert!(resolve(parts(&["Food=100", "Home"]), total).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_parts() {
+        let total = Decimal::new(100, 0);
+        assert!(resolve(parts(&["Food"]), total).is_err());
+        assert!(resolve(parts(&["Food", "Home"]), total).is_err());
+        assert!(resolve(parts(&["Food=-10", "Home"]), total).is_err());
+        assert!(resolve(parts(&["Food=0.001", "Home"]), total).is_err());
+        assert!("=10".parse::<SplitPart>().is_err());
+        assert!("Food=ten".parse::<SplitPart>().is_err());
+    }
+}
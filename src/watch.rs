@@ -0,0 +1,248 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::Result;
+use color_eyre::eyre::{Context, OptionExt};
+use console::Emoji;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebouncedEventKind, Debouncer, new_debouncer};
+
+use crate::budget::{self, BudgetPlan};
+use crate::categories::CategoryTree;
+use crate::config::AppConfig;
+use crate::db::{self, Db};
+use crate::importer::categorizer::Categorizer;
+use crate::importer::source::{SourceFile, WatchedAccounts};
+use crate::importer::suggester::Suggester;
+use crate::importer::{self, ImportContext};
+use crate::output::OutputMode;
+
+/// How long a file must go unchanged before it is read, so downloads and synced files
+/// are complete
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy)]
+struct WatchOptions {
+    output: OutputMode,
+    verbose: bool,
+}
+
+/// Everything built from the config file, replaced when it changes
+struct Rules {
+    config: &'static AppConfig,
+    categories: &'static CategoryTree,
+    categorizer: &'static Categorizer,
+    budgets: BudgetPlan,
+    accounts: WatchedAccounts<'static>,
+}
+
+impl Rules {
+    /// Replaced rules are leaked along with their config, which is fine for occasional edits
+    async fn load(config_path: &Path) -> Result<Self> {
+        let config = crate::load_config(config_path.to_path_buf())
+            .await
+            .map(|c| &*Box::leak(Box::new(c)))?;
+        let categories = CategoryTree::build(&config.category)
+            .map(|c| &*Box::leak(Box::new(c)))
+            .wrap_err("Failed to load categories")?;
+        let categorizer = Categorizer::build(&config.transaction_type, &config.rule, categories)
+            .map(|c| &*Box::leak(Box::new(c)))
+            .wrap_err("Failed to load transaction rules")?;
+        let budgets =
+            BudgetPlan::build(&config.budget, categories).wrap_err("Failed to load budgets")?;
+        let accounts = WatchedAccounts::new(&config.account)?;
+
+        Ok(Self {
+            config,
+            categories,
+            categorizer,
+            budgets,
+            accounts,
+        })
+    }
+
+    fn watch(&self, debouncer: &mut Debouncer<RecommendedWatcher>) -> Result<()> {
+        for root in self.accounts.roots() {
+            debouncer
+                .watcher()
+                .watch(root, RecursiveMode::Recursive)
+                .wrap_err_with(|| format!("Failed to watch {}", root.to_string_lossy()))?;
+        }
+        Ok(())
+    }
+
+    fn unwatch(&self, debouncer: &mut Debouncer<RecommendedWatcher>) {
+        for root in self.accounts.roots() {
+            // Fails for directories that were deleted, which are no longer watched anyway
+            let _ = debouncer.watcher().unwatch(root);
+        }
+    }
+}
+
+/// Import `files`, or everything in the account source directories without any
+async fn import(
+    db: &Db,
+    rules: &Rules,
+    files: Option<Vec<SourceFile>>,
+    options: WatchOptions,
+) -> Result<()> {
+    importer::migrate_keys(db).await?;
+    let mut conn = db.open_handle().await?;
+    conn.sync_categories(rules.categories).await?;
+    let categorized = conn.list_categorized_names().await?;
+    // Overrides set since the last import apply to new files
+    let overrides = conn.list_overrides().await?;
+    drop(conn);
+
+    let suggester = Suggester::build(&rules.config.rule, categorized);
+    let context = ImportContext {
+        db,
+        categorizer: rules.categorizer,
+        suggester: &suggester,
+        overrides: &overrides,
+        verbose: options.verbose,
+        output: options.output,
+        duplicates: AtomicUsize::new(0),
+    };
+
+    match files {
+        None => importer::import_files(&context, &rules.config.account).await?,
+        Some(files) => {
+            for file in files {
+                let path = file.path.clone();
+                if let Err(e) = importer::import_source(&context, file).await {
+                    eprintln!("Failed to import {}: {:#}", path.to_string_lossy(), e);
+                }
+            }
+        }
+    }
+    context.report_duplicates();
+
+    if !rules.budgets.is_empty() {
+        budget::refresh(db, &rules.budgets).await?;
+    }
+
+    Ok(())
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Import new files in the account source directories as they appear, until interrupted.
+/// Editing the config reloads the rules and accounts.
+pub async fn watch(config_path: &Path, output: OutputMode, verbose: bool) -> Result<()> {
+    // Progress bars don't suit a long running process, so log each file instead
+    let options = WatchOptions {
+        output: match output {
+            OutputMode::Fancy => OutputMode::Plain,
+            other => other,
+        },
+        verbose,
+    };
+
+    let config_path = tokio::fs::canonicalize(config_path)
+        .await
+        .wrap_err_with(|| format!("Failed to find config: {}", config_path.to_string_lossy()))?;
+    // Editors replace the file instead of writing to it, so watch its directory
+    let config_dir = config_path
+        .parent()
+        .ok_or_eyre("Config file has no parent directory")?;
+
+    let mut config_modified = modified(&config_path).await;
+    let mut rules = Rules::load(&config_path).await?;
+    let db = db::build(&rules.config.database, false)
+        .await
+        .wrap_err("Failed to setup DB")?;
+
+    let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, move |result| {
+        let _ = event_tx.send(result);
+    })
+    .wrap_err("Failed to start watching")?;
+    debouncer
+        .watcher()
+        .watch(config_dir, RecursiveMode::NonRecursive)
+        .wrap_err("Failed to watch the config directory")?;
+    rules.watch(&mut debouncer)?;
+
+    // Catch up on files added while not running
+    if let Err(e) = import(&db, &rules, None, options).await {
+        eprintln!("Import failed: {:#}", e);
+    }
+    options.output.step(
+        "👀 ",
+        format!(
+            "Watching {} accounts for new statements",
+            rules.config.account.len()
+        ),
+    );
+
+    while let Some(result) = event_rx.recv().await {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Watch error: {}", e);
+                continue;
+            }
+        };
+        // Files still being written show up as AnyContinuous until they settle
+        let paths = events
+            .into_iter()
+            .filter(|e| e.kind == DebouncedEventKind::Any)
+            .map(|e| e.path)
+            .collect::<BTreeSet<PathBuf>>();
+
+        // Reading the config shows up as an event too, so check it actually changed
+        if paths.contains(&config_path)
+            && let Some(modified) = modified(&config_path).await
+            && config_modified.replace(modified) != Some(modified)
+        {
+            match Rules::load(&config_path).await {
+                Ok(new_rules) => {
+                    // The pool keeps its connections, so the old settings stay in use
+                    let database_changed = new_rules.config.database != rules.config.database;
+                    rules.unwatch(&mut debouncer);
+                    rules = new_rules;
+                    rules.watch(&mut debouncer)?;
+                    options
+                        .output
+                        .step("📄 ", format!("Reloaded {}", config_path.to_string_lossy()));
+                    if database_changed {
+                        eprintln!(
+                            "{}Changes to [database] take effect after a restart",
+                            Emoji("⚠️ ", "")
+                        );
+                    }
+
+                    // Source directories may have changed
+                    if let Err(e) = import(&db, &rules, None, options).await {
+                        eprintln!("Import failed: {:#}", e);
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Keeping the previous config, failed to reload {}: {:#}",
+                    config_path.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+
+        // Source directories are matched by their canonical path, and events may not use it
+        let files = paths
+            .iter()
+            .filter_map(|p| std::fs::canonicalize(p).ok())
+            .filter(|p| p.is_file())
+            .flat_map(|p| rules.accounts.find(&p, verbose))
+            .collect::<Vec<_>>();
+        if !files.is_empty()
+            && let Err(e) = import(&db, &rules, Some(files), options).await
+        {
+            eprintln!("Import failed: {:#}", e);
+        }
+    }
+
+    Ok(())
+}